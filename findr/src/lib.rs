@@ -1,8 +1,16 @@
 use crate::EntryType::*;
-use clap::{Arg, Command};
-use regex::Regex;
+use clap::Command;
+use glob::{MatchOptions, Pattern};
+use regex::{Regex, RegexBuilder};
+use std::env;
 use std::error::Error;
-use std::fs;
+use std::ffi::{OsStr, OsString};
+use std::fs::{self, Metadata};
+use std::io::{self, Write};
+use std::mem;
+use std::path::Path;
+use std::process::{self, ExitStatus, Stdio};
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
 use walkdir::DirEntry;
 use walkdir::WalkDir;
 
@@ -15,122 +23,848 @@ enum EntryType {
     Link,
 }
 
+//how an entry compares to a number given as [+-]N:
+//+N is more than N, -N is less than N, and N is exactly N
+#[derive(Debug, PartialEq)]
+enum Cmp {
+    Less,
+    Exactly,
+    More,
+}
+
+//the timestamp of an entry that --atime, --mtime and --ctime look at
+#[derive(Clone, Copy, Debug, PartialEq)]
+enum Time {
+    Access,
+    Modify,
+    Change,
+}
+
+//tests that need the metadata of an entry
+#[derive(Debug, PartialEq)]
+enum MetaTest {
+    //the size in units of the given number of bytes
+    Size(Cmp, u64, u64),
+    //how many days ago the timestamp was
+    Days(Time, Cmp, u64),
+    //how many minutes ago the timestamp was
+    Minutes(Time, Cmp, u64),
+    //modified later than the reference file was
+    Newer(SystemTime),
+}
+
+//a single test on an entry, which is where new predicates plug in
+#[derive(Debug)]
+enum Test {
+    //any of the globs matches the file name
+    Name(Vec<Pattern>, MatchOptions),
+    //any of the globs matches the whole path
+    Path(Vec<Pattern>, MatchOptions),
+    //anchored on both ends of the whole path
+    Regex(Regex),
+    //the entry is any of the types
+    Type(Vec<EntryType>),
+    Meta(MetaTest),
+}
+
+//what to do with an entry, any of them turns off printing every match
+#[derive(Debug)]
+enum Action {
+    Print,
+    //runs the command for every entry with {} replaced by the path,
+    //asking first with -ok, and matches when it exits successfully
+    Exec { argv: Vec<String>, prompt: bool },
+    Batch(Batch),
+    //removes the entry, which needs the walk to give the contents first
+    Delete,
+}
+
+//the paths -exec ... {} + has collected, which are put at the end of the
+//command once there is no room for another one in ARG_MAX
+#[derive(Debug)]
+struct Batch {
+    argv: Vec<String>,
+    paths: Vec<OsString>,
+    //how many bytes are left for the paths
+    room: usize,
+    left: usize,
+    //the first run that failed, which makes the whole search fail
+    failed: Option<ExitStatus>,
+}
+
+//the expression the entries are filtered with, built by Parser
+#[derive(Debug)]
+enum Expr {
+    And(Box<Expr>, Box<Expr>),
+    Or(Box<Expr>, Box<Expr>),
+    Not(Box<Expr>),
+    Test(Test),
+    Action(Action),
+    //doesn't descend into the directory, unless the contents go first
+    Prune,
+    //options like -maxdepth, which apply to the whole walk
+    True,
+}
+
 #[derive(Debug)]
 pub struct Config {
     dirs: Vec<String>,
-    names: Option<Vec<Regex>>,
-    entry_types: Option<Vec<EntryType>>,
+    //None prints every entry
+    expr: Option<Expr>,
+    min_depth: Option<usize>,
+    max_depth: Option<usize>,
 }
 
-pub fn get_args() -> MyResult<Config> {
-    let matches = Command::new("findr")
+//clap can't keep the order of the tests and operators, so it is only used
+//for --help and --version and the expression is parsed by hand
+fn command() -> Command<'static> {
+    Command::new("findr")
         .version("0.1.0")
         .author("Lev")
         .about("Rust Find")
-        .arg(
-            Arg::new("dirs")
-                .value_name("DIR")
-                .help("Search directory")
-                .takes_value(true)
-                .default_value(".")
-                .allow_invalid_utf8(true)
-                .min_values(1),
-        )
-        .arg(
-            Arg::new("name")
-                .short('n')
-                .long("name")
-                .help("name")
-                .value_name("NAME")
-                .allow_invalid_utf8(true)
-                .takes_value(true)
-                .multiple(true),
-        )
-        .arg(
-            Arg::new("type")
-                .short('t')
-                .long("type")
-                .value_name("TYPE")
-                .help("Entry type")
-                .takes_value(true)
-                .possible_values(["f", "d", "l"])
-                .multiple(true)
-                .allow_invalid_utf8(true),
+        .override_usage("findr [DIR...] [EXPRESSION]")
+        .after_help(
+            "TESTS:
+    -n, -name GLOB...         File name matches any of the globs
+    -iname GLOB...            Like -name, ignoring case
+    -path GLOB...             Whole path matches any of the globs, * takes / too
+    -ipath GLOB...            Like -path, ignoring case
+    -regex REGEX              Whole path matches the regex
+    -iregex REGEX             Like -regex, ignoring case
+    -t, -type TYPE...         Entry type is any of f, d or l
+    -size [+-]N[cwbkMG]       File size, in 512-byte blocks unless a unit is given
+    -atime [+-]N              Days since last accessed
+    -mtime [+-]N              Days since last modified
+    -ctime [+-]N              Days since the status last changed
+    -mmin [+-]N               Minutes since last modified
+    -newer FILE               Modified more recently than FILE
+    -prune                    Always true, and doesn't walk into the directory
+                              unless with -delete
+
+ACTIONS, which stop every match from being printed:
+    -print                    Print the path
+    -exec CMD [ARG...] ;      Run CMD with {} in ARG replaced by the path,
+                              true when it exits successfully
+    -exec CMD [ARG...] {} +   Run CMD with as many paths at the end as fit
+    -ok CMD [ARG...] ;        Like -exec, but ask first
+    -delete                   Delete the entry, contents first
+
+OPTIONS, which apply wherever they are:
+    -mindepth N               Skip the entries less than N levels down
+    -maxdepth N               Don't walk more than N levels down
+
+OPERATORS, from the highest precedence:
+    ( EXPR )
+    ! EXPR, -not EXPR
+    EXPR EXPR, EXPR -a EXPR, EXPR -and EXPR
+    EXPR -o EXPR, EXPR -or EXPR
+
+Tests can also be given with two dashes, like --name.",
         )
-        .get_matches();
-
-    //handling the names option, which is a vector of regex
-    let mut names = vec![];
-
-    if let Some(values) = matches.values_of_lossy("name") {
-        for name in values {
-            //create a new regex
-            match Regex::new(&name) {
-                Ok(exp) => names.push(exp),
-                _ => return Err(From::from(format!("Invalid --name \"{}\"", name))),
-            }
-        }
-    }
-
-    let entry_types = matches.values_of_lossy("type").map(|vals| {
-        vals.iter()
-            .filter_map(|val| match val.as_str() {
-                "d" => Some(Dir),
-                "f" => Some(File),
-                "l" => Some(Link),
-                _ => None,
-            })
-            .collect()
-    });
-
-    Ok(Config {
-        dirs: matches.values_of_lossy("dirs").unwrap(),
-        names: if names.is_empty() { None } else { Some(names) },
-        entry_types,
-    })
-}
-
-pub fn run(config: Config) -> MyResult<()> {
-    //Approach: use closures alonside with filter
-
-    //filter file types
-    let type_filter = |entry: &DirEntry| match &config.entry_types {
-        Some(types) => types.iter().any(|t| match t {
-            Link => entry.path_is_symlink(),
-            Dir => entry.file_type().is_dir(),
-            File => entry.file_type().is_file(),
-        }),
-        _ => true,
+}
+
+pub fn get_args() -> MyResult<Config> {
+    let args = env::args_os()
+        .skip(1)
+        .map(|arg| arg.to_string_lossy().into_owned())
+        .collect();
+    Parser {
+        args,
+        pos: 0,
+        min_depth: None,
+        max_depth: None,
+    }
+    .parse()
+}
+
+//the tokens that start an expression, anything before them is a directory
+fn is_expr_start(token: &str) -> bool {
+    token.starts_with('-') || token == "(" || token == "!"
+}
+
+//a recursive descent parser with the precedence of GNU find:
+//or := and (-o and)*
+//and := not ([-a] not)*
+//not := ! not | primary
+//primary := ( or ) | test
+struct Parser {
+    args: Vec<String>,
+    pos: usize,
+    min_depth: Option<usize>,
+    max_depth: Option<usize>,
+}
+
+impl Parser {
+    fn parse(mut self) -> MyResult<Config> {
+        while self.peek().is_some_and(|token| !is_expr_start(token)) {
+            self.pos += 1;
+        }
+        let mut dirs = self.args[..self.pos].to_vec();
+        if dirs.is_empty() {
+            dirs.push(".".to_string());
+        }
+
+        let expr = match self.peek() {
+            Some(_) => Some(self.parse_or()?),
+            None => None,
+        };
+        match self.peek() {
+            None => Ok(Config {
+                dirs,
+                expr,
+                min_depth: self.min_depth,
+                max_depth: self.max_depth,
+            }),
+            //parse_and stops at a ) only
+            Some(_) => Err(self.error(self.pos, "Unexpected \")\"".to_string())),
+        }
+    }
+
+    fn peek(&self) -> Option<&str> {
+        self.args.get(self.pos).map(String::as_str)
+    }
+
+    fn parse_or(&mut self) -> MyResult<Expr> {
+        let mut expr = self.parse_and()?;
+        while let Some("-o" | "-or") = self.peek() {
+            self.pos += 1;
+            expr = Expr::Or(Box::new(expr), Box::new(self.parse_and()?));
+        }
+        Ok(expr)
+    }
+
+    fn parse_and(&mut self) -> MyResult<Expr> {
+        let mut expr = self.parse_not()?;
+        loop {
+            match self.peek() {
+                None | Some("-o" | "-or" | ")") => return Ok(expr),
+                Some("-a" | "-and") => self.pos += 1,
+                //two tests next to each other are and'ed
+                Some(_) => {}
+            }
+            expr = Expr::And(Box::new(expr), Box::new(self.parse_not()?));
+        }
+    }
+
+    fn parse_not(&mut self) -> MyResult<Expr> {
+        match self.peek() {
+            Some("!" | "-not") => {
+                self.pos += 1;
+                Ok(Expr::Not(Box::new(self.parse_not()?)))
+            }
+            _ => self.parse_primary(),
+        }
+    }
+
+    fn parse_primary(&mut self) -> MyResult<Expr> {
+        let pos = self.pos;
+        let token = match self.args.get(pos) {
+            Some(token) => token.clone(),
+            //there is always a token before, or there would be no expression
+            None => {
+                let msg = format!("Expected an expression after \"{}\"", self.args[pos - 1]);
+                return Err(self.error(pos - 1, msg));
+            }
+        };
+        self.pos += 1;
+
+        let test = match token.as_str() {
+            "(" => {
+                let expr = self.parse_or()?;
+                if self.peek() != Some(")") {
+                    return Err(self.error(pos, "Unmatched \"(\"".to_string()));
+                }
+                self.pos += 1;
+                return Ok(expr);
+            }
+            "-h" | "--help" => {
+                command().print_help()?;
+                process::exit(0);
+            }
+            "-V" | "--version" => {
+                print!("{}", command().render_version());
+                process::exit(0);
+            }
+            "-n" | "-name" | "--name" => Test::Name(self.globs(pos)?, glob_options(true)),
+            "-iname" | "--iname" => Test::Name(self.globs(pos)?, glob_options(false)),
+            "-path" | "--path" | "-wholename" | "--wholename" => {
+                Test::Path(self.globs(pos)?, glob_options(true))
+            }
+            "-ipath" | "--ipath" | "-iwholename" | "--iwholename" => {
+                Test::Path(self.globs(pos)?, glob_options(false))
+            }
+            "-regex" | "--regex" | "-iregex" | "--iregex" => {
+                let val = self.value(pos)?;
+                match RegexBuilder::new(&format!("^(?:{})$", val))
+                    .case_insensitive(token.ends_with("iregex"))
+                    .build()
+                {
+                    Ok(re) => Test::Regex(re),
+                    _ => return Err(self.invalid(pos + 1, &token, &val)),
+                }
+            }
+            "-t" | "-type" | "--type" => {
+                let mut types = vec![];
+                for (pos, val) in self.values(pos)? {
+                    types.push(match val.as_str() {
+                        "d" => Dir,
+                        "f" => File,
+                        "l" => Link,
+                        _ => return Err(self.invalid(pos, &token, &val)),
+                    });
+                }
+                Test::Type(types)
+            }
+            "-size" | "--size" => {
+                let val = self.value(pos)?;
+                match parse_size(&val) {
+                    Some(test) => Test::Meta(test),
+                    None => return Err(self.invalid(pos + 1, &token, &val)),
+                }
+            }
+            "-atime" | "--atime" => Test::Meta(self.days(pos, Time::Access)?),
+            "-mtime" | "--mtime" => Test::Meta(self.days(pos, Time::Modify)?),
+            "-ctime" | "--ctime" => Test::Meta(self.days(pos, Time::Change)?),
+            "-mmin" | "--mmin" => {
+                let (cmp, n) = self.number(pos)?;
+                Test::Meta(MetaTest::Minutes(Time::Modify, cmp, n))
+            }
+            //like GNU find, a symlink given as the reference file is not followed
+            "-newer" | "--newer" => {
+                let file = self.value(pos)?;
+                let modified = fs::symlink_metadata(&file)
+                    .and_then(|metadata| metadata.modified())
+                    .map_err(|e| format!("{}: {}", file, e))?;
+                Test::Meta(MetaTest::Newer(modified))
+            }
+            "-print" | "--print" => return Ok(Expr::Action(Action::Print)),
+            "-exec" | "--exec" => {
+                let (argv, batch) = self.exec_args(pos, true)?;
+                return Ok(Expr::Action(match batch {
+                    true => Action::Batch(Batch::new(argv)),
+                    false => Action::Exec {
+                        argv,
+                        prompt: false,
+                    },
+                }));
+            }
+            "-ok" | "--ok" => {
+                let (argv, _) = self.exec_args(pos, false)?;
+                return Ok(Expr::Action(Action::Exec { argv, prompt: true }));
+            }
+            "-delete" | "--delete" => return Ok(Expr::Action(Action::Delete)),
+            "-prune" | "--prune" => return Ok(Expr::Prune),
+            //like GNU find, these are options wherever they are, the last one wins
+            "-mindepth" | "--mindepth" => {
+                self.min_depth = Some(self.depth(pos)?);
+                return Ok(Expr::True);
+            }
+            "-maxdepth" | "--maxdepth" => {
+                self.max_depth = Some(self.depth(pos)?);
+                return Ok(Expr::True);
+            }
+            ")" | "-o" | "-or" | "-a" | "-and" => {
+                return Err(self.error(pos, format!("Unexpected \"{}\"", token)));
+            }
+            _ if token.starts_with('-') => {
+                return Err(self.error(pos, format!("Unknown predicate \"{}\"", token)));
+            }
+            _ => {
+                let msg = format!("Paths must precede the expression, found \"{}\"", token);
+                return Err(self.error(pos, msg));
+            }
+        };
+        Ok(Expr::Test(test))
+    }
+
+    //the single value of the test at pos
+    fn value(&mut self, pos: usize) -> MyResult<String> {
+        match self.args.get(self.pos) {
+            Some(val) => {
+                self.pos += 1;
+                Ok(val.clone())
+            }
+            None => Err(self.error(pos, format!("Missing argument to \"{}\"", self.args[pos]))),
+        }
+    }
+
+    //the values of a test that takes more than one, with their positions:
+    //the first one can be anything, the rest stop at the next test or operator
+    fn values(&mut self, pos: usize) -> MyResult<Vec<(usize, String)>> {
+        let mut values = vec![(self.pos, self.value(pos)?)];
+        while let Some(val) = self.peek() {
+            if is_expr_start(val) || val == ")" {
+                break;
+            }
+            values.push((self.pos, val.to_string()));
+            self.pos += 1;
+        }
+        Ok(values)
+    }
+
+    //the command of the -exec at pos up to the ; or, when batch is allowed,
+    //the {} + that ends it, and whether it was the latter
+    fn exec_args(&mut self, pos: usize, batch: bool) -> MyResult<(Vec<String>, bool)> {
+        let mut argv = vec![];
+        while let Some(arg) = self.args.get(self.pos) {
+            self.pos += 1;
+            match arg.as_str() {
+                ";" if !argv.is_empty() => return Ok((argv, false)),
+                "+" if batch && argv.len() > 1 && argv.last().unwrap() == "{}" => {
+                    argv.pop();
+                    return Ok((argv, true));
+                }
+                _ => argv.push(arg.clone()),
+            }
+        }
+        Err(self.error(pos, format!("Missing argument to \"{}\"", self.args[pos])))
+    }
+
+    fn depth(&mut self, pos: usize) -> MyResult<usize> {
+        let val = self.value(pos)?;
+        match val.parse() {
+            Ok(depth) if val.bytes().all(|b| b.is_ascii_digit()) => Ok(depth),
+            _ => Err(self.invalid(pos + 1, &self.args[pos], &val)),
+        }
+    }
+
+    fn globs(&mut self, pos: usize) -> MyResult<Vec<Pattern>> {
+        let mut globs = vec![];
+        for (val_pos, val) in self.values(pos)? {
+            match Pattern::new(&val) {
+                Ok(glob) => globs.push(glob),
+                _ => return Err(self.invalid(val_pos, &self.args[pos], &val)),
+            }
+        }
+        Ok(globs)
+    }
+
+    fn number(&mut self, pos: usize) -> MyResult<(Cmp, u64)> {
+        let val = self.value(pos)?;
+        parse_number(&val).ok_or_else(|| self.invalid(pos + 1, &self.args[pos], &val))
+    }
+
+    fn days(&mut self, pos: usize, time: Time) -> MyResult<MetaTest> {
+        let (cmp, n) = self.number(pos)?;
+        Ok(MetaTest::Days(time, cmp, n))
+    }
+
+    fn invalid(&self, pos: usize, token: &str, val: &str) -> Box<dyn Error> {
+        self.error(pos, format!("Invalid {} \"{}\"", token, val))
+    }
+
+    //the message followed by the command line with the token at pos underlined
+    fn error(&self, pos: usize, msg: String) -> Box<dyn Error> {
+        let mut line = "findr".to_string();
+        let mut caret = String::new();
+        for (i, arg) in self.args.iter().enumerate() {
+            line.push(' ');
+            line.push_str(arg);
+            if i == pos {
+                caret = " ".repeat(line.chars().count() - arg.chars().count());
+                caret.push_str(&"^".repeat(arg.chars().count().max(1)));
+            }
+        }
+        From::from(format!("{}\n{}\n{}", msg, line, caret))
+    }
+}
+
+//like fnmatch in GNU find, * matches a leading dot and, in -path, a /
+fn glob_options(case_sensitive: bool) -> MatchOptions {
+    MatchOptions {
+        case_sensitive,
+        require_literal_separator: false,
+        require_literal_leading_dot: false,
+    }
+}
+
+//splits a number given as [+-]N into how to compare with it and N itself
+fn parse_number(val: &str) -> Option<(Cmp, u64)> {
+    let (cmp, num) = match val.as_bytes().first() {
+        Some(b'+') => (Cmp::More, &val[1..]),
+        Some(b'-') => (Cmp::Less, &val[1..]),
+        _ => (Cmp::Exactly, val),
+    };
+    //parse would take another sign
+    if num.is_empty() || !num.bytes().all(|b| b.is_ascii_digit()) {
+        return None;
+    }
+    num.parse().ok().map(|n| (cmp, n))
+}
+
+//parses a size given as [+-]N[cwbkMG], where the unit is bytes, 2-byte words,
+//512-byte blocks, KiB, MiB or GiB, and blocks when there is none, like in GNU find
+fn parse_size(val: &str) -> Option<MetaTest> {
+    let (num, unit) = match val.char_indices().last() {
+        Some((i, c)) if c.is_ascii_alphabetic() => (&val[..i], c),
+        _ => (val, 'b'),
     };
+    let unit = match unit {
+        'c' => Some(1),
+        'w' => Some(2),
+        'b' => Some(512),
+        'k' => Some(1024),
+        'M' => Some(1024 * 1024),
+        'G' => Some(1024 * 1024 * 1024),
+        _ => None,
+    };
+    let (cmp, n) = parse_number(num)?;
+    Some(MetaTest::Size(cmp, n, unit?))
+}
+
+impl MetaTest {
+    //the rounding follows GNU find: sizes are rounded up to the unit, days are
+    //whole days since, with -N taking in one more second, and minutes are rounded up,
+    //but +N and -N compare with the exact number of seconds
+    fn is_match(&self, metadata: &Metadata, now: SystemTime) -> MyResult<bool> {
+        Ok(match self {
+            MetaTest::Size(cmp, n, unit) => {
+                let size = metadata.len().div_ceil(*unit);
+                match cmp {
+                    Cmp::Less => size < *n,
+                    Cmp::Exactly => size == *n,
+                    Cmp::More => size > *n,
+                }
+            }
+            MetaTest::Days(time, cmp, n) => {
+                let age = age(now, timestamp(metadata, time)?);
+                let n = *n as f64;
+                match cmp {
+                    Cmp::Less => age < n * DAY + 1.0,
+                    Cmp::Exactly => (age / DAY).floor() == n,
+                    Cmp::More => (age / DAY).floor() > n,
+                }
+            }
+            MetaTest::Minutes(time, cmp, n) => {
+                let age = age(now, timestamp(metadata, time)?);
+                let n = *n as f64;
+                match cmp {
+                    Cmp::Less => age < n * 60.0,
+                    Cmp::Exactly => (age / 60.0).ceil() == n,
+                    Cmp::More => age > n * 60.0,
+                }
+            }
+            MetaTest::Newer(reference) => metadata.modified()? > *reference,
+        })
+    }
+}
+
+const DAY: f64 = 24.0 * 60.0 * 60.0;
+
+//how many seconds ago the time was, less than zero when it is in the future
+fn age(now: SystemTime, time: SystemTime) -> f64 {
+    match now.duration_since(time) {
+        Ok(age) => age.as_secs_f64(),
+        Err(e) => -e.duration().as_secs_f64(),
+    }
+}
+
+fn timestamp(metadata: &Metadata, time: &Time) -> io::Result<SystemTime> {
+    match time {
+        Time::Access => metadata.accessed(),
+        Time::Modify => metadata.modified(),
+        Time::Change => changed(metadata),
+    }
+}
+
+#[cfg(unix)]
+fn changed(metadata: &Metadata) -> io::Result<SystemTime> {
+    use std::os::unix::fs::MetadataExt;
+    let secs = Duration::from_secs(metadata.ctime().max(0) as u64);
+    Ok(UNIX_EPOCH + secs + Duration::from_nanos(metadata.ctime_nsec() as u64))
+}
+
+//there is no status change time elsewhere, the creation time is the closest
+#[cfg(not(unix))]
+fn changed(metadata: &Metadata) -> io::Result<SystemTime> {
+    metadata.created()
+}
+
+impl Expr {
+    //short-circuits like GNU find, so the metadata is only fetched, once,
+    //when a test that needs it is reached, and actions only run on the
+    //entries the tests before them matched, and -prune sets prune
+    fn is_match(
+        &mut self,
+        entry: &DirEntry,
+        metadata: &mut Option<Metadata>,
+        prune: &mut bool,
+        now: SystemTime,
+    ) -> MyResult<bool> {
+        Ok(match self {
+            Expr::And(left, right) => {
+                left.is_match(entry, metadata, prune, now)?
+                    && right.is_match(entry, metadata, prune, now)?
+            }
+            Expr::Or(left, right) => {
+                left.is_match(entry, metadata, prune, now)?
+                    || right.is_match(entry, metadata, prune, now)?
+            }
+            Expr::Not(expr) => !expr.is_match(entry, metadata, prune, now)?,
+            Expr::Test(test) => test.is_match(entry, metadata, now)?,
+            Expr::Action(action) => action.run(entry)?,
+            Expr::Prune => {
+                *prune = true;
+                true
+            }
+            Expr::True => true,
+        })
+    }
+
+    fn any_action(&self, f: &dyn Fn(&Action) -> bool) -> bool {
+        match self {
+            Expr::And(left, right) | Expr::Or(left, right) => {
+                left.any_action(f) || right.any_action(f)
+            }
+            Expr::Not(expr) => expr.any_action(f),
+            Expr::Test(_) | Expr::Prune | Expr::True => false,
+            Expr::Action(action) => f(action),
+        }
+    }
+
+    //runs what the batches still hold once the walk is done
+    fn finish(&mut self) -> MyResult<()> {
+        match self {
+            Expr::And(left, right) | Expr::Or(left, right) => {
+                left.finish()?;
+                right.finish()
+            }
+            Expr::Not(expr) => expr.finish(),
+            Expr::Test(_) | Expr::Prune | Expr::True => Ok(()),
+            Expr::Action(Action::Batch(batch)) => {
+                batch.run()?;
+                match batch.failed {
+                    Some(status) => Err(From::from(format!("{}: {}", batch.argv[0], status))),
+                    _ => Ok(()),
+                }
+            }
+            Expr::Action(_) => Ok(()),
+        }
+    }
+}
+
+impl Action {
+    fn run(&mut self, entry: &DirEntry) -> MyResult<bool> {
+        let path = entry.path();
+        match self {
+            Action::Print => {
+                println!("{}", path.display());
+                Ok(true)
+            }
+            Action::Exec { argv, prompt } => {
+                let argv: Vec<OsString> = argv.iter().map(|arg| with_path(arg, path)).collect();
+                let mut cmd = process::Command::new(&argv[0]);
+                cmd.args(&argv[1..]);
+                //like GNU find, the answer is read from stdin, so the command can't have it
+                if *prompt {
+                    eprint!(
+                        "< {} ... {} > ? ",
+                        argv[0].to_string_lossy(),
+                        path.display()
+                    );
+                    io::stderr().flush()?;
+                    let mut answer = String::new();
+                    io::stdin().read_line(&mut answer)?;
+                    if !answer.trim_start().starts_with(['y', 'Y']) {
+                        return Ok(false);
+                    }
+                    cmd.stdin(Stdio::null());
+                }
+                let status = cmd
+                    .status()
+                    .map_err(|e| format!("{}: {}", argv[0].to_string_lossy(), e))?;
+                Ok(status.success())
+            }
+            //the batch is only known to have failed once it runs
+            Action::Batch(batch) => {
+                batch.push(path.as_os_str())?;
+                Ok(true)
+            }
+            //GNU find leaves the current directory alone too
+            Action::Delete if path == Path::new(".") => Ok(true),
+            Action::Delete => {
+                match entry.file_type().is_dir() {
+                    true => fs::remove_dir(path)?,
+                    false => fs::remove_file(path)?,
+                }
+                Ok(true)
+            }
+        }
+    }
+}
+
+//the argument with every {} replaced by the path, which keeps the bytes of a
+//path that isn't UTF-8
+fn with_path(arg: &str, path: &Path) -> OsString {
+    let mut with = OsString::new();
+    for (i, part) in arg.split("{}").enumerate() {
+        if i > 0 {
+            with.push(path);
+        }
+        with.push(part);
+    }
+    with
+}
+
+impl Batch {
+    fn new(argv: Vec<String>) -> Batch {
+        let size = argv.iter().map(arg_size).sum();
+        let room = arg_max().saturating_sub(size);
+        Batch {
+            argv,
+            paths: vec![],
+            room,
+            left: room,
+            failed: None,
+        }
+    }
 
-    let name_filter = |entry: &DirEntry| match &config.names {
-        //config.names can be null -> need a match statement
-        //
-        Some(names) => names
-            .iter()
-            .any(|re| re.is_match(&entry.file_name().to_string_lossy())),
-        _ => true,
+    //a path that doesn't fit even on its own still gets a run
+    fn push(&mut self, path: &OsStr) -> MyResult<()> {
+        let size = arg_size(path);
+        if !self.paths.is_empty() && size > self.left {
+            self.run()?;
+        }
+        self.left = self.left.saturating_sub(size);
+        self.paths.push(path.to_os_string());
+        Ok(())
+    }
+
+    fn run(&mut self) -> MyResult<()> {
+        if self.paths.is_empty() {
+            return Ok(());
+        }
+        let status = process::Command::new(&self.argv[0])
+            .args(&self.argv[1..])
+            .args(&self.paths)
+            .status()
+            .map_err(|e| format!("{}: {}", self.argv[0], e))?;
+        if !status.success() && self.failed.is_none() {
+            self.failed = Some(status);
+        }
+        self.paths.clear();
+        self.left = self.room;
+        Ok(())
+    }
+}
+
+//what an argument takes up of ARG_MAX: the string with its nul and the pointer to it
+fn arg_size<S: AsRef<OsStr>>(arg: S) -> usize {
+    arg.as_ref().len() + 1 + mem::size_of::<usize>()
+}
+
+//ARG_MAX less the environment, which shares it, and the 2048 bytes POSIX
+//says to leave for the command to change its environment
+#[cfg(unix)]
+fn arg_max() -> usize {
+    let max = unsafe { libc::sysconf(libc::_SC_ARG_MAX) };
+    //4096 is the least POSIX allows
+    let max = if max > 0 { max as usize } else { 4096 };
+    let env: usize = env::vars_os()
+        .map(|(key, val)| key.len() + val.len() + 2 + mem::size_of::<usize>())
+        .sum();
+    max.saturating_sub(env).saturating_sub(2048)
+}
+
+//the longest command line windows takes, in characters
+#[cfg(not(unix))]
+fn arg_max() -> usize {
+    32 * 1024 - 2048
+}
+
+impl Test {
+    fn is_match(
+        &self,
+        entry: &DirEntry,
+        metadata: &mut Option<Metadata>,
+        now: SystemTime,
+    ) -> MyResult<bool> {
+        Ok(match self {
+            Test::Name(globs, options) => {
+                let name = entry.file_name().to_string_lossy();
+                globs.iter().any(|glob| glob.matches_with(&name, *options))
+            }
+            Test::Path(globs, options) => {
+                let path = entry.path().to_string_lossy();
+                globs.iter().any(|glob| glob.matches_with(&path, *options))
+            }
+            Test::Regex(re) => re.is_match(&entry.path().to_string_lossy()),
+            Test::Type(types) => types.iter().any(|t| match t {
+                Link => entry.path_is_symlink(),
+                Dir => entry.file_type().is_dir(),
+                File => entry.file_type().is_file(),
+            }),
+            Test::Meta(test) => {
+                if metadata.is_none() {
+                    *metadata = Some(entry.metadata()?);
+                }
+                test.is_match(metadata.as_ref().unwrap(), now)?
+            }
+        })
+    }
+}
+
+//returns the exit status, which is 1 when an entry failed, e.g. a -delete,
+//after the others have still been gone through, like GNU find does
+pub fn run(config: Config) -> MyResult<i32> {
+    //the times are compared with when the search started, like GNU find does
+    let now = SystemTime::now();
+
+    let mut expr = config.expr;
+    let print = !expr.as_ref().is_some_and(|expr| expr.any_action(&|_| true));
+    let delete = expr
+        .as_ref()
+        .is_some_and(|expr| expr.any_action(&|action| matches!(action, Action::Delete)));
+
+    //whether the entry matched and whether to walk into it
+    let mut failed = false;
+    let mut filter = |entry: &DirEntry| {
+        let mut prune = false;
+        let matched = match &mut expr {
+            Some(expr) => expr
+                .is_match(entry, &mut None, &mut prune, now)
+                .unwrap_or_else(|e| {
+                    eprintln!("{}: {}", entry.path().display(), e);
+                    failed = true;
+                    false
+                }),
+            _ => true,
+        };
+        //the directory has been walked already when its contents go first
+        (matched, !prune || delete)
     };
 
     //iterate through all input directory paths
-    for dirname in config.dirs {
+    for dirname in &config.dirs {
         //Walkdir creates an iterator to iterate recursively through directories
         //to check if a string is a directory, use fs::read_dir
-        match fs::read_dir(&dirname) {
+        match fs::read_dir(dirname) {
             Err(e) => eprintln!("{}: {}", dirname, e),
             _ => {
-                let entries = WalkDir::new(dirname)
-                    .into_iter()
-                    .filter_map(|e| e.ok())
-                    .filter(type_filter)
-                    .filter(name_filter)
-                    .map(|entry| entry.path().display().to_string())
-                    .collect::<Vec<String>>();
-
-                println!("{}", entries.join("\n"));
+                let mut walk = WalkDir::new(dirname).contents_first(delete);
+                if let Some(depth) = config.min_depth {
+                    walk = walk.min_depth(depth);
+                }
+                if let Some(depth) = config.max_depth {
+                    walk = walk.max_depth(depth);
+                }
+                //filter_entry is the only way to keep the walk out of a directory,
+                //so the matches are printed on the side, and errors are skipped
+                walk.into_iter()
+                    .filter_entry(|entry| {
+                        let (matched, descend) = filter(entry);
+                        if matched && print {
+                            println!("{}", entry.path().display());
+                        }
+                        descend
+                    })
+                    .for_each(drop);
             }
         }
     }
 
-    Ok(())
+    if let Some(expr) = &mut expr {
+        expr.finish()?;
+    }
+    Ok(if failed { 1 } else { 0 })
 }
@@ -1,6 +1,9 @@
 fn main() {
-    if let Err(e) = findr::get_args().and_then(findr::run) {
-        eprint!("{}", e);
-        std::process::exit(1);
+    match findr::get_args().and_then(findr::run) {
+        Ok(status) => std::process::exit(status),
+        Err(e) => {
+            eprintln!("{}", e);
+            std::process::exit(1);
+        }
     }
 }
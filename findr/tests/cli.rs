@@ -1,7 +1,12 @@
 use assert_cmd::Command;
 use predicates::prelude::*;
 use rand::{distributions::Alphanumeric, Rng};
-use std::{borrow::Cow, fs, path::Path};
+use std::{
+    borrow::Cow,
+    fs::{self, File, FileTimes},
+    path::Path,
+    time::{Duration, SystemTime},
+};
 
 type TestResult = Result<(), Box<dyn std::error::Error>>;
 
@@ -39,19 +44,19 @@ fn skips_bad_dir() -> TestResult {
 #[test]
 fn dies_bad_name() -> TestResult {
     Command::cargo_bin(PRG)?
-        .args(&["--name", "*.csv"])
+        .args(["--name", "[.csv"])
         .assert()
         .failure()
-        .stderr(predicate::str::contains("Invalid --name \"*.csv\""));
+        .stderr(predicate::str::contains("Invalid --name \"[.csv\""));
     Ok(())
 }
 
 // --------------------------------------------------
 #[test]
 fn dies_bad_type() -> TestResult {
-    let expected = "error: \"x\" isn't a valid value for '--type <TYPE>...'";
+    let expected = "Invalid --type \"x\"";
     Command::cargo_bin(PRG)?
-        .args(&["--type", "x"])
+        .args(["--type", "x"])
         .assert()
         .failure()
         .stderr(predicate::str::contains(expected));
@@ -60,14 +65,14 @@ fn dies_bad_type() -> TestResult {
 
 // --------------------------------------------------
 #[cfg(windows)]
-fn format_file_name(expected_file: &str) -> Cow<str> {
+fn format_file_name(expected_file: &str) -> Cow<'_, str> {
     // Equivalent to: Cow::Owned(format!("{}.windows", expected_file))
     format!("{}.windows", expected_file).into()
 }
 
 // --------------------------------------------------
 #[cfg(not(windows))]
-fn format_file_name(expected_file: &str) -> Cow<str> {
+fn format_file_name(expected_file: &str) -> Cow<'_, str> {
     // Equivalent to: Cow::Borrowed(expected_file)
     expected_file.into()
 }
@@ -226,7 +231,7 @@ fn type_f_l() -> TestResult {
 #[test]
 fn name_csv() -> TestResult {
     run(
-        &["tests/inputs", "-n", ".*[.]csv"],
+        &["tests/inputs", "-n", "*.csv"],
         "tests/expected/name_csv.txt",
     )
 }
@@ -235,7 +240,7 @@ fn name_csv() -> TestResult {
 #[test]
 fn name_csv_mp3() -> TestResult {
     run(
-        &["tests/inputs", "-n", ".*[.]csv", "-n", ".*[.]mp3"],
+        &["tests/inputs", "-n", "*.csv", "-o", "-n", "*.mp3"],
         "tests/expected/name_csv_mp3.txt",
     )
 }
@@ -244,7 +249,7 @@ fn name_csv_mp3() -> TestResult {
 #[test]
 fn name_txt_path_a_d() -> TestResult {
     run(
-        &["tests/inputs/a", "tests/inputs/d", "--name", ".*.txt"],
+        &["tests/inputs/a", "tests/inputs/d", "--name", "*.txt"],
         "tests/expected/name_txt_path_a_d.txt",
     )
 }
@@ -252,14 +257,14 @@ fn name_txt_path_a_d() -> TestResult {
 // --------------------------------------------------
 #[test]
 fn name_a() -> TestResult {
-    run(&["tests/inputs", "-n", "a"], "tests/expected/name_a.txt")
+    run(&["tests/inputs", "-n", "a*"], "tests/expected/name_a.txt")
 }
 
 // --------------------------------------------------
 #[test]
 fn type_f_name_a() -> TestResult {
     run(
-        &["tests/inputs", "-t", "f", "-n", "a"],
+        &["tests/inputs", "-t", "f", "-n", "a*"],
         "tests/expected/type_f_name_a.txt",
     )
 }
@@ -268,11 +273,202 @@ fn type_f_name_a() -> TestResult {
 #[test]
 fn type_d_name_a() -> TestResult {
     run(
-        &["tests/inputs", "--type", "d", "--name", "a"],
+        &["tests/inputs", "--type", "d", "--name", "a*"],
         "tests/expected/type_d_name_a.txt",
     )
 }
 
+// --------------------------------------------------
+#[test]
+fn iname_csv() -> TestResult {
+    run(
+        &["tests/inputs", "-iname", "*.CSV"],
+        "tests/expected/name_csv.txt",
+    )
+}
+
+// --------------------------------------------------
+#[test]
+fn name_regex_anchored() -> TestResult {
+    run(
+        &["tests/inputs", "-name", "csv", "-o", "-regex", "b.csv"],
+        "tests/expected/name_regex_anchored.txt",
+    )
+}
+
+// --------------------------------------------------
+//run drops empty lines, so this checks that nothing at all is printed
+#[test]
+fn no_matches() -> TestResult {
+    for args in [
+        ["tests/inputs", "-name", "zzz"],
+        ["tests/inputs", "-mindepth", "9"],
+    ] {
+        Command::cargo_bin(PRG)?
+            .args(args)
+            .assert()
+            .success()
+            .stdout("");
+    }
+    Ok(())
+}
+
+// --------------------------------------------------
+#[test]
+fn ipath() -> TestResult {
+    run(
+        &["tests/inputs", "-ipath", "*D*.T?T"],
+        "tests/expected/ipath.txt",
+    )
+}
+
+// --------------------------------------------------
+#[test]
+fn regex() -> TestResult {
+    run(
+        &["tests/inputs", "-regex", ".*[ab].*[.]csv"],
+        "tests/expected/regex.txt",
+    )?;
+    run(
+        &["tests/inputs", "-iregex", ".*[AB].*[.]CSV"],
+        "tests/expected/regex.txt",
+    )
+}
+
+// --------------------------------------------------
+#[test]
+fn dies_bad_regex() -> TestResult {
+    dies_parse(
+        &["-regex", "("],
+        "Invalid -regex \"(\"\nfindr -regex (\n             ^\n",
+    )?;
+    dies_parse(&["-path", "a[", "-o"], "Invalid -path \"a[\"")
+}
+
+// --------------------------------------------------
+#[test]
+fn or_not_parens() -> TestResult {
+    run(
+        &[
+            "tests/inputs",
+            "(",
+            "-name",
+            "*.csv",
+            "-o",
+            "-name",
+            "*.txt",
+            ")",
+            "!",
+            "-name",
+            "d*",
+        ],
+        "tests/expected/or_not_parens.txt",
+    )
+}
+
+// --------------------------------------------------
+#[test]
+fn and_before_or() -> TestResult {
+    run(
+        &["tests/inputs", "-t", "f", "-n", "*.mp3", "-o", "-n", "a*"],
+        "tests/expected/and_before_or.txt",
+    )
+}
+
+// --------------------------------------------------
+#[test]
+fn not_and() -> TestResult {
+    run(
+        &[
+            "tests/inputs",
+            "-not",
+            "-type",
+            "d",
+            "-a",
+            "-not",
+            "-n",
+            "*csv*",
+        ],
+        "tests/expected/not_and.txt",
+    )
+}
+
+// --------------------------------------------------
+#[test]
+fn maxdepth_1() -> TestResult {
+    run(
+        &["tests/inputs", "-maxdepth", "1"],
+        "tests/expected/maxdepth_1.txt",
+    )
+}
+
+// --------------------------------------------------
+#[test]
+fn mindepth_2_maxdepth_2() -> TestResult {
+    run(
+        &["tests/inputs", "-mindepth", "2", "--maxdepth", "2"],
+        "tests/expected/mindepth_2_maxdepth_2.txt",
+    )
+}
+
+// --------------------------------------------------
+#[test]
+fn prune() -> TestResult {
+    run(
+        &["tests/inputs", "-name", "[ad]", "-prune", "-o", "-print"],
+        "tests/expected/prune.txt",
+    )
+}
+
+// --------------------------------------------------
+#[test]
+fn dies_bad_depth() -> TestResult {
+    dies_parse(
+        &["-maxdepth", "-1"],
+        "Invalid -maxdepth \"-1\"\nfindr -maxdepth -1\n                ^^\n",
+    )?;
+    dies_parse(&["-mindepth"], "Missing argument to \"-mindepth\"")
+}
+
+// --------------------------------------------------
+fn dies_parse(args: &[&str], expected: &str) -> TestResult {
+    Command::cargo_bin(PRG)?
+        .args(args)
+        .assert()
+        .failure()
+        .stderr(predicate::str::contains(expected));
+    Ok(())
+}
+
+// --------------------------------------------------
+#[test]
+fn dies_bad_expressions() -> TestResult {
+    dies_parse(
+        &["tests/inputs", "-n", "a", "-foo"],
+        "Unknown predicate \"-foo\"\nfindr tests/inputs -n a -foo\n                        ^^^^\n",
+    )?;
+    dies_parse(
+        &["(", "-n", "a", "-o", "-n", "b"],
+        "Unmatched \"(\"\nfindr ( -n a -o -n b\n      ^\n",
+    )?;
+    dies_parse(
+        &["-n", "a", ")"],
+        "Unexpected \")\"\nfindr -n a )\n           ^\n",
+    )?;
+    dies_parse(
+        &["-n", "a", "-o"],
+        "Expected an expression after \"-o\"\nfindr -n a -o\n           ^^\n",
+    )?;
+    dies_parse(
+        &["-t", "f", "-n"],
+        "Missing argument to \"-n\"\nfindr -t f -n\n           ^^\n",
+    )?;
+    dies_parse(
+        &["-type", "f", "-mtime", "1", "tests"],
+        "Paths must precede the expression, found \"tests\"",
+    )
+}
+
 // --------------------------------------------------
 #[test]
 fn path_g() -> TestResult {
@@ -293,7 +489,7 @@ fn unreadable_dir() -> TestResult {
     //permissions.set_mode(0o000);
 
     std::process::Command::new("chmod")
-        .args(&["000", dirname])
+        .args(["000", dirname])
         .status()
         .expect("failed");
 
@@ -314,3 +510,312 @@ fn unreadable_dir() -> TestResult {
     assert!(stderr.contains("cant-touch-this: Permission denied"));
     Ok(())
 }
+
+// --------------------------------------------------
+//a temporary directory, which is removed once the test is done with it,
+//even when an assert fails
+struct TempDir(String);
+
+impl TempDir {
+    fn path(&self) -> &str {
+        &self.0
+    }
+}
+
+impl Drop for TempDir {
+    fn drop(&mut self) {
+        let _ = fs::remove_dir_all(&self.0);
+    }
+}
+
+// --------------------------------------------------
+//makes a directory of files with the given sizes, last accessed and modified
+//the given number of minutes ago
+fn make_dir(files: &[(&str, usize, u64)]) -> Result<TempDir, Box<dyn std::error::Error>> {
+    let dir = TempDir(
+        std::env::temp_dir()
+            .join(format!("findr-{}", gen_bad_file()))
+            .display()
+            .to_string(),
+    );
+    fs::create_dir(dir.path())?;
+    let now = SystemTime::now();
+    for (name, size, minutes) in files {
+        let path = format!("{}/{}", dir.path(), name);
+        fs::write(&path, vec![b'x'; *size])?;
+        let time = now - Duration::from_secs(minutes * 60);
+        File::options()
+            .write(true)
+            .open(&path)?
+            .set_times(FileTimes::new().set_accessed(time).set_modified(time))?;
+    }
+    Ok(dir)
+}
+
+// --------------------------------------------------
+//runs findr on dirname and returns the names it found, without the directory
+fn find_in(dirname: &str, args: &[&str]) -> Result<Vec<String>, Box<dyn std::error::Error>> {
+    let cmd = Command::cargo_bin(PRG)?
+        .arg(dirname)
+        .args(["--type", "f"])
+        .args(args)
+        .assert()
+        .success();
+    let stdout = String::from_utf8(cmd.get_output().stdout.clone())?;
+    let prefix = format!("{}/", dirname);
+    let mut names: Vec<String> = stdout
+        .lines()
+        .filter_map(|line| line.strip_prefix(&prefix).map(String::from))
+        .collect();
+    names.sort();
+    Ok(names)
+}
+
+// --------------------------------------------------
+#[test]
+#[cfg(not(windows))]
+fn size() -> TestResult {
+    let dir = make_dir(&[
+        ("empty", 0, 0),
+        ("small", 100, 0),
+        ("block", 513, 0),
+        ("big", 2000, 0),
+    ])?;
+    let dirname = dir.path();
+
+    //sizes are rounded up to the unit, so -1k only finds empty files
+    assert_eq!(find_in(dirname, &["--size", "-1k"])?, ["empty"]);
+    assert_eq!(find_in(dirname, &["--size", "1k"])?, ["block", "small"]);
+    assert_eq!(find_in(dirname, &["--size", "+1k"])?, ["big"]);
+    //512-byte blocks without a unit
+    assert_eq!(find_in(dirname, &["--size", "2"])?, ["block"]);
+    assert_eq!(find_in(dirname, &["--size", "2000c"])?, ["big"]);
+    //every test has to match
+    assert_eq!(
+        find_in(dirname, &["--size", "+0", "--size", "-2"])?,
+        ["small"]
+    );
+    Ok(())
+}
+
+// --------------------------------------------------
+#[test]
+#[cfg(not(windows))]
+fn mtime_mmin_newer() -> TestResult {
+    let dir = make_dir(&[
+        ("now", 1, 0),
+        ("hour", 1, 90),
+        ("day", 1, 36 * 60),
+        ("week", 1, 7 * 24 * 60),
+    ])?;
+    let dirname = dir.path();
+
+    //whole days since, so +1 takes two days at least
+    assert_eq!(find_in(dirname, &["--mtime", "0"])?, ["hour", "now"]);
+    assert_eq!(find_in(dirname, &["--mtime", "1"])?, ["day"]);
+    assert_eq!(find_in(dirname, &["--mtime", "+1"])?, ["week"]);
+    assert_eq!(
+        find_in(dirname, &["--mtime", "-2"])?,
+        ["day", "hour", "now"]
+    );
+    assert_eq!(find_in(dirname, &["--mmin", "-60"])?, ["now"]);
+    assert_eq!(
+        find_in(dirname, &["--mmin", "+60", "--mmin", "-120"])?,
+        ["hour"]
+    );
+    let reference = format!("{}/day", dirname);
+    assert_eq!(find_in(dirname, &["--newer", &reference])?, ["hour", "now"]);
+    Ok(())
+}
+
+// --------------------------------------------------
+#[test]
+fn dies_bad_size() -> TestResult {
+    Command::cargo_bin(PRG)?
+        .args(["--size", "+1x"])
+        .assert()
+        .failure()
+        .stderr(predicate::str::contains("Invalid --size \"+1x\""));
+    Ok(())
+}
+
+// --------------------------------------------------
+#[test]
+fn dies_bad_mtime() -> TestResult {
+    Command::cargo_bin(PRG)?
+        .args(["--mtime", "+-1"])
+        .assert()
+        .failure()
+        .stderr(predicate::str::contains("Invalid --mtime \"+-1\""));
+    Ok(())
+}
+
+// --------------------------------------------------
+#[test]
+fn dies_bad_newer() -> TestResult {
+    let bad = gen_bad_file();
+    let expected = format!("{}: .* [(]os error 2[)]", &bad);
+    Command::cargo_bin(PRG)?
+        .args(["--newer", &bad])
+        .assert()
+        .failure()
+        .stderr(predicate::str::is_match(expected)?);
+    Ok(())
+}
+
+// --------------------------------------------------
+#[test]
+#[cfg(not(windows))]
+fn exec() -> TestResult {
+    //runs once per entry and matches when the command succeeds
+    Command::cargo_bin(PRG)?
+        .args([
+            "tests/inputs",
+            "-type",
+            "f",
+            "-exec",
+            "test",
+            "{}",
+            "=",
+            "tests/inputs/g.csv",
+            ";",
+        ])
+        .args(["-print"])
+        .assert()
+        .success()
+        .stdout("tests/inputs/g.csv\n");
+
+    //the paths are all given to the one run
+    let cmd = Command::cargo_bin(PRG)?
+        .args([
+            "tests/inputs",
+            "-name",
+            "*.csv",
+            "-exec",
+            "echo",
+            "found",
+            "{}",
+            "+",
+        ])
+        .assert()
+        .success();
+    let stdout = String::from_utf8(cmd.get_output().stdout.clone())?;
+    let mut words: Vec<&str> = stdout.split_whitespace().collect();
+    words.sort();
+    assert_eq!(stdout.lines().count(), 1);
+    assert_eq!(
+        words,
+        [
+            "found",
+            "tests/inputs/a/b/b.csv",
+            "tests/inputs/d/b.csv",
+            "tests/inputs/g.csv"
+        ]
+    );
+
+    Command::cargo_bin(PRG)?
+        .args(["tests/inputs", "-exec", "false", "{}", "+"])
+        .assert()
+        .failure()
+        .stderr("false: exit status: 1\n");
+    Ok(())
+}
+
+// --------------------------------------------------
+//the path reaches the command as it is, even when it isn't UTF-8
+#[test]
+#[cfg(target_os = "linux")]
+fn exec_non_utf8_name() -> TestResult {
+    use std::{ffi::OsStr, os::unix::ffi::OsStrExt};
+
+    let dir = make_dir(&[])?;
+    let dirname = dir.path();
+    fs::write(
+        Path::new(dirname).join(OsStr::from_bytes(b"caf\xe9")),
+        "hi\n",
+    )?;
+    for end in [";", "+"] {
+        Command::cargo_bin(PRG)?
+            .args([dirname, "-type", "f", "-exec", "cat", "{}", end])
+            .assert()
+            .success()
+            .stdout("hi\n");
+    }
+    Ok(())
+}
+
+// --------------------------------------------------
+#[test]
+#[cfg(not(windows))]
+fn ok() -> TestResult {
+    let args = [
+        "tests/inputs",
+        "-name",
+        "g.csv",
+        "-ok",
+        "echo",
+        "hi",
+        "{}",
+        ";",
+    ];
+    Command::cargo_bin(PRG)?
+        .args(args)
+        .write_stdin("y\n")
+        .assert()
+        .success()
+        .stdout("hi tests/inputs/g.csv\n")
+        .stderr("< echo ... tests/inputs/g.csv > ? ");
+    Command::cargo_bin(PRG)?
+        .args(args)
+        .write_stdin("n\n")
+        .assert()
+        .success()
+        .stdout("");
+    Ok(())
+}
+
+// --------------------------------------------------
+#[test]
+#[cfg(not(windows))]
+fn delete() -> TestResult {
+    let dir = make_dir(&[("a", 0, 0), ("b", 0, 0)])?;
+    let dirname = dir.path();
+    fs::create_dir(format!("{}/c", dirname))?;
+    fs::write(format!("{}/c/a", dirname), "")?;
+
+    //nothing is printed once there is an action
+    Command::cargo_bin(PRG)?
+        .args([dirname, "-name", "a", "-delete"])
+        .assert()
+        .success()
+        .stdout("");
+    assert_eq!(find_in(dirname, &[])?, ["b"]);
+
+    //a directory that isn't empty stays, and the search fails
+    fs::write(format!("{}/c/d", dirname), "")?;
+    Command::cargo_bin(PRG)?
+        .args([dirname, "-name", "c", "-delete"])
+        .assert()
+        .failure()
+        .stderr(predicate::str::contains(format!("{}/c: ", dirname)));
+    assert_eq!(find_in(dirname, &[])?, ["b", "c/d"]);
+
+    //the contents go first, so the directories are empty when they are deleted
+    Command::cargo_bin(PRG)?
+        .args([dirname, "-delete"])
+        .assert()
+        .success();
+    assert!(!Path::new(dirname).exists());
+    Ok(())
+}
+
+// --------------------------------------------------
+#[test]
+fn dies_bad_exec() -> TestResult {
+    dies_parse(
+        &["-exec", "echo", "{}"],
+        "Missing argument to \"-exec\"\nfindr -exec echo {}\n      ^^^^^\n",
+    )?;
+    //only -exec can batch
+    dies_parse(&["-ok", "echo", "{}", "+"], "Missing argument to \"-ok\"")
+}
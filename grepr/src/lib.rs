@@ -1,16 +1,307 @@
-use clap::{App, Arg};
-use regex::{Regex, RegexBuilder};
+use aho_corasick::{AhoCorasick, AhoCorasickBuilder, MatchKind};
+use bzip2::bufread::MultiBzDecoder;
+use clap::{App, Arg, ArgMatches};
+use encoding_rs::Encoding;
+use encoding_rs_io::{DecodeReaderBytes, DecodeReaderBytesBuilder};
+use flate2::bufread::MultiGzDecoder;
+use ignore::overrides::{Override, OverrideBuilder};
+use ignore::{Walk, WalkBuilder};
+use regex::bytes::{Regex, RegexBuilder};
+use serde_json::{json, Value};
+use std::borrow::Cow;
+use std::collections::{HashMap, VecDeque};
+use std::env;
 use std::error::Error;
+use std::fs::{self, File};
+use std::io::{self, BufRead, BufReader, Cursor, IsTerminal, Read, Write};
+use std::mem;
+use std::ops::Range;
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::{mpsc, Arc, Mutex};
+use std::thread;
+use std::time::{Duration, Instant};
+use xz2::bufread::XzDecoder;
+use zstd::stream::read::Decoder as ZstdDecoder;
 
 type MyResult<T> = Result<T, Box<dyn Error>>;
 
 #[derive(Debug)]
 pub struct Config {
-    pattern: Regex,
+    //the pattern, along with the options that decide which lines are selected
+    searcher: Searcher,
     files: Vec<String>,
     recursive: bool,
     count: bool,
-    invert_match: bool,
+    only_matching: bool,
+    colors: Option<Colors>,
+    json: bool,
+    walk: WalkOptions,
+    threads: usize,
+    unordered: bool,
+    line_number: bool,
+    byte_offset: bool,
+    //forces the filename prefix on or off, instead of guessing from the inputs
+    with_filename: Option<bool>,
+    null: bool,
+    list_files: Option<ListFiles>,
+    quiet: bool,
+    //what the matches are replaced with in the output
+    replace: Option<String>,
+    count_matches: bool,
+    //print a summary of the search to stderr at the end
+    stats: bool,
+}
+
+//which paths -l and -L print, in place of the lines
+#[derive(Debug, PartialEq)]
+enum ListFiles {
+    WithMatches,
+    WithoutMatch,
+}
+
+//what to do with inputs that look binary, as in GNU grep's --binary-files
+#[derive(Debug, PartialEq)]
+pub enum BinaryFiles {
+    //search them, but tell them apart with Search::binary, so that their
+    //lines aren't printed
+    Binary,
+    //search them like any other input
+    Text,
+    //don't search them at all
+    WithoutMatch,
+}
+
+//what gets skipped when walking directories in recursive mode
+#[derive(Debug, Default)]
+struct WalkOptions {
+    //search hidden files and directories too
+    hidden: bool,
+    //don't read .gitignore, .ignore and the like
+    no_ignore: bool,
+    //globs that include or, when starting with '!', exclude paths
+    globs: Vec<String>,
+}
+
+//what the lines are searched with, built from one or more patterns by MatcherBuilder
+#[derive(Debug)]
+pub struct Matcher(Engine);
+
+//a regex, or a set of fixed strings with -F
+#[derive(Debug)]
+enum Engine {
+    Regex(Regex),
+    Literals(Box<AhoCorasick>),
+    //a regex whose matches only count between non-word characters, for -w
+    //whole is the same regex anchored at both ends, to try shorter matches with
+    Words { re: Regex, whole: Regex },
+}
+
+//what a match must span for -w and -x
+#[derive(Debug, PartialEq)]
+enum Boundary {
+    Word,
+    Line,
+}
+
+impl Matcher {
+    //a matcher for a single regex, with every option turned off
+    pub fn new(pattern: &str) -> MyResult<Matcher> {
+        MatcherBuilder::new().build(&[pattern])
+    }
+
+    pub fn is_match(&self, text: &[u8]) -> bool {
+        match &self.0 {
+            Engine::Regex(re) => re.is_match(text),
+            Engine::Literals(ac) => ac.is_match(text),
+            Engine::Words { .. } => self.spans(text).next().is_some(),
+        }
+    }
+
+    //the byte ranges of the matches in the text, skipping empty ones
+    pub fn find_iter<'a>(&'a self, text: &'a [u8]) -> Box<dyn Iterator<Item = Range<usize>> + 'a> {
+        Box::new(self.spans(text).filter(|span| !span.is_empty()))
+    }
+
+    //the byte ranges of the non-empty matches in the text, each with the template
+    //expanded for it, where $1 and ${name} stand for the groups it captured
+    //fixed strings have no groups, so the template is used as it is
+    fn replacements(&self, text: &[u8], template: &[u8]) -> Vec<(Range<usize>, Vec<u8>)> {
+        match &self.0 {
+            Engine::Regex(re) => re
+                .captures_iter(text)
+                .filter_map(|caps| {
+                    let span = caps.get(0).map(|m| m.range()).filter(|m| !m.is_empty())?;
+                    let mut replaced = vec![];
+                    caps.expand(template, &mut replaced);
+                    Some((span, replaced))
+                })
+                .collect(),
+            Engine::Literals(_) => self
+                .find_iter(text)
+                .map(|span| (span, template.to_vec()))
+                .collect(),
+            //the groups are captured again from the match alone
+            Engine::Words { whole, .. } => self
+                .find_iter(text)
+                .map(|span| {
+                    let mut replaced = vec![];
+                    if let Some(caps) = whole.captures(&text[span.clone()]) {
+                        caps.expand(template, &mut replaced);
+                    }
+                    (span, replaced)
+                })
+                .collect(),
+        }
+    }
+
+    //the byte ranges of all the matches in the text, empty ones included
+    fn spans<'a>(&'a self, text: &'a [u8]) -> Box<dyn Iterator<Item = Range<usize>> + 'a> {
+        match &self.0 {
+            Engine::Regex(re) => Box::new(re.find_iter(text).map(|m| m.range())),
+            Engine::Literals(ac) => Box::new(ac.find_iter(text).map(|m| m.start()..m.end())),
+            Engine::Words { re, whole } => Box::new(word_spans(re, whole, text).into_iter()),
+        }
+    }
+}
+
+//the options a Matcher is built with, the ones of -i, -F, --multiline-dotall, -w and -x
+#[derive(Debug, Default)]
+pub struct MatcherBuilder {
+    case_insensitive: bool,
+    fixed_strings: bool,
+    multiline_dotall: bool,
+    word_regexp: bool,
+    line_regexp: bool,
+}
+
+impl MatcherBuilder {
+    pub fn new() -> Self {
+        MatcherBuilder::default()
+    }
+
+    pub fn case_insensitive(&mut self, yes: bool) -> &mut Self {
+        self.case_insensitive = yes;
+        self
+    }
+
+    //take the patterns as literal strings rather than regexes
+    pub fn fixed_strings(&mut self, yes: bool) -> &mut Self {
+        self.fixed_strings = yes;
+        self
+    }
+
+    //let '.' match a newline, which only matters to a multiline Searcher
+    pub fn multiline_dotall(&mut self, yes: bool) -> &mut Self {
+        self.multiline_dotall = yes;
+        self
+    }
+
+    //only match whole words
+    pub fn word_regexp(&mut self, yes: bool) -> &mut Self {
+        self.word_regexp = yes;
+        self
+    }
+
+    //only match whole lines
+    pub fn line_regexp(&mut self, yes: bool) -> &mut Self {
+        self.line_regexp = yes;
+        self
+    }
+
+    //a matcher where any of the patterns can match
+    //-x wins over -w, as a whole line is bounded by non-word characters anyway
+    pub fn build<S: AsRef<str>>(&self, patterns: &[S]) -> MyResult<Matcher> {
+        let patterns: Vec<String> = patterns.iter().map(|p| p.as_ref().to_string()).collect();
+        let boundary = if self.line_regexp {
+            Some(Boundary::Line)
+        } else if self.word_regexp {
+            Some(Boundary::Word)
+        } else {
+            None
+        };
+        build_pattern(
+            &patterns,
+            self.case_insensitive,
+            self.fixed_strings,
+            self.multiline_dotall,
+            boundary,
+        )
+    }
+}
+
+//the matches that start and end next to a non-word character or the edge of the text
+//as in GNU grep, a match that isn't bounded is tried shorter from the same start,
+//and then the search goes on from the next byte, so that a later match can still count
+fn word_spans(re: &Regex, whole: &Regex, text: &[u8]) -> Vec<Range<usize>> {
+    let mut spans = vec![];
+    let mut start = 0;
+    while start <= text.len() {
+        let Some(m) = re.find_at(text, start) else {
+            break;
+        };
+        //no match can start inside a word, so go on from where the word ends
+        if char_before(text, m.start()).is_some_and(is_word_char) {
+            start = m.start() + 1;
+            while start <= text.len() && char_before(text, start).is_some_and(is_word_char) {
+                start += 1;
+            }
+            continue;
+        }
+        let bounded = |end: usize| !char_after(text, end).is_some_and(is_word_char);
+        let found = (m.start()..=m.end())
+            .rev()
+            .find(|&end| bounded(end) && (end == m.end() || whole.is_match(&text[m.start()..end])));
+        match found {
+            Some(end) => {
+                spans.push(m.start()..end);
+                start = if end > m.start() { end } else { end + 1 };
+            }
+            None => start = m.start() + 1,
+        }
+    }
+    spans
+}
+
+fn is_word_char(c: char) -> bool {
+    c.is_alphanumeric() || c == '_'
+}
+
+//the character that ends right before the offset, if it is valid UTF-8
+fn char_before(text: &[u8], offset: usize) -> Option<char> {
+    (1..=offset.min(4))
+        .find_map(|n| std::str::from_utf8(&text[offset - n..offset]).ok())
+        .and_then(|s| s.chars().next_back())
+}
+
+//the character that starts at the offset, if it is valid UTF-8
+fn char_after(text: &[u8], offset: usize) -> Option<char> {
+    (1..=(text.len() - offset).min(4))
+        .find_map(|n| std::str::from_utf8(&text[offset..offset + n]).ok())
+        .and_then(|s| s.chars().next())
+}
+
+//SGR sequences used to color the output, in the format of GNU grep's GREP_COLORS
+#[derive(Debug, PartialEq)]
+struct Colors {
+    matched: String,
+    filename: String,
+    line_number: String,
+    byte_offset: String,
+    separator: String,
+}
+
+impl Default for Colors {
+    //the same defaults as GNU grep
+    fn default() -> Self {
+        Colors {
+            matched: "01;31".to_string(),
+            filename: "35".to_string(),
+            line_number: "32".to_string(),
+            byte_offset: "32".to_string(),
+            separator: "36".to_string(),
+        }
+    }
 }
 
 pub fn get_args() -> MyResult<Config> {
@@ -22,7 +313,7 @@ pub fn get_args() -> MyResult<Config> {
             Arg::with_name("pattern")
                 .value_name("PATTERN")
                 .help("Search pattern")
-                .required(true),
+                .required_unless_one(&["regexp", "pattern_file"]),
         )
         .arg(
             Arg::with_name("files")
@@ -39,6 +330,20 @@ pub fn get_args() -> MyResult<Config> {
                 .takes_value(false)
                 .required(false),
         )
+        .arg(
+            Arg::with_name("word_regexp")
+                .help("Only match whole words")
+                .short("w")
+                .long("word-regexp")
+                .takes_value(false),
+        )
+        .arg(
+            Arg::with_name("line_regexp")
+                .help("Only match whole lines")
+                .short("x")
+                .long("line-regexp")
+                .takes_value(false),
+        )
         .arg(
             Arg::with_name("recursive")
                 .help("Recursive search")
@@ -55,6 +360,18 @@ pub fn get_args() -> MyResult<Config> {
                 .takes_value(false)
                 .required(false),
         )
+        .arg(
+            Arg::with_name("count_matches")
+                .help("Count the matches, including several on the same line")
+                .long("count-matches")
+                .takes_value(false),
+        )
+        .arg(
+            Arg::with_name("stats")
+                .help("Print how much was searched and matched to stderr at the end")
+                .long("stats")
+                .takes_value(false),
+        )
         .arg(
             Arg::with_name("invert")
                 .help("Invert search")
@@ -63,24 +380,2573 @@ pub fn get_args() -> MyResult<Config> {
                 .takes_value(false)
                 .required(false),
         )
-        .get_matches();
+        .arg(
+            Arg::with_name("after_context")
+                .value_name("NUM")
+                .help("Print NUM lines of trailing context")
+                .short("A")
+                .long("after-context"),
+        )
+        .arg(
+            Arg::with_name("before_context")
+                .value_name("NUM")
+                .help("Print NUM lines of leading context")
+                .short("B")
+                .long("before-context"),
+        )
+        .arg(
+            Arg::with_name("context")
+                .value_name("NUM")
+                .help("Print NUM lines of output context")
+                .short("C")
+                .long("context"),
+        )
+        .arg(
+            Arg::with_name("only_matching")
+                .help("Print only the matched parts of a line")
+                .short("o")
+                .long("only-matching")
+                .takes_value(false),
+        )
+        .arg(
+            Arg::with_name("color")
+                .value_name("WHEN")
+                .help("Highlight matches, filenames and separators")
+                .long("color")
+                .possible_values(&["auto", "always", "never"])
+                //a bare --color means auto, and the value must be attached with '='
+                //so that --color doesn't swallow the pattern
+                .min_values(0)
+                .require_equals(true),
+        )
+        .arg(
+            Arg::with_name("json")
+                .help("Print the results as JSON lines")
+                .long("json")
+                .takes_value(false)
+                .conflicts_with_all(&[
+                    "count",
+                    "count_matches",
+                    "only_matching",
+                    "files_with_matches",
+                    "files_without_match",
+                ]),
+        )
+        .arg(
+            Arg::with_name("hidden")
+                .help("Search hidden files and directories when recursing")
+                .long("hidden")
+                .takes_value(false),
+        )
+        .arg(
+            Arg::with_name("no_ignore")
+                .help("Don't respect .gitignore, .ignore and git exclude files")
+                .long("no-ignore")
+                .takes_value(false),
+        )
+        .arg(
+            Arg::with_name("glob")
+                .value_name("GLOB")
+                .help("Include or, with a leading '!', exclude files when recursing")
+                .short("g")
+                .long("glob")
+                .multiple(true)
+                .number_of_values(1),
+        )
+        .arg(
+            Arg::with_name("threads")
+                .value_name("NUM")
+                .help("Number of files to search at once [default: number of CPUs]")
+                .short("j")
+                .long("threads"),
+        )
+        .arg(
+            Arg::with_name("unordered")
+                .help("Write results as soon as they are found, in no particular order")
+                .long("unordered")
+                .takes_value(false),
+        )
+        .arg(
+            Arg::with_name("binary_files")
+                .value_name("TYPE")
+                .help("How to handle binary files")
+                .long("binary-files")
+                .possible_values(&["binary", "text", "without-match"])
+                .default_value("binary"),
+        )
+        .arg(
+            Arg::with_name("text")
+                .help("Search binary files as if they were text, same as --binary-files=text")
+                .short("a")
+                .long("text")
+                .takes_value(false),
+        )
+        .arg(
+            Arg::with_name("line_number")
+                .help("Prefix each line with its line number")
+                .short("n")
+                .long("line-number")
+                .takes_value(false),
+        )
+        .arg(
+            Arg::with_name("byte_offset")
+                .help("Prefix each line (or match, with -o) with its byte offset")
+                .short("b")
+                .long("byte-offset")
+                .takes_value(false),
+        )
+        .arg(
+            Arg::with_name("with_filename")
+                .help("Prefix each line with the filename")
+                .short("H")
+                .long("with-filename")
+                .takes_value(false)
+                .overrides_with("no_filename"),
+        )
+        .arg(
+            Arg::with_name("no_filename")
+                .help("Never prefix lines with the filename")
+                .short("h")
+                .long("no-filename")
+                .takes_value(false)
+                .overrides_with("with_filename"),
+        )
+        .arg(
+            Arg::with_name("null")
+                .help("Write a NUL byte after each filename instead of ':'")
+                .short("Z")
+                .long("null")
+                .takes_value(false),
+        )
+        .arg(
+            Arg::with_name("files_with_matches")
+                .help("Only print the paths of files with a match")
+                .short("l")
+                .long("files-with-matches")
+                .takes_value(false)
+                .overrides_with("files_without_match"),
+        )
+        .arg(
+            Arg::with_name("files_without_match")
+                .help("Only print the paths of files without a match")
+                .short("L")
+                .long("files-without-match")
+                .takes_value(false)
+                .overrides_with("files_with_matches"),
+        )
+        .arg(
+            Arg::with_name("max_count")
+                .value_name("NUM")
+                .help("Stop reading a file after NUM selected lines")
+                .short("m")
+                .long("max-count"),
+        )
+        .arg(
+            Arg::with_name("quiet")
+                .help("Print nothing, exit with 0 at the first match")
+                .short("q")
+                .long("quiet")
+                .alias("silent")
+                .takes_value(false),
+        )
+        .arg(
+            Arg::with_name("multiline")
+                .help("Search whole files, so that matches can span lines")
+                .short("U")
+                .long("multiline")
+                .takes_value(false),
+        )
+        .arg(
+            Arg::with_name("multiline_dotall")
+                .help("Let '.' match newlines too in multiline mode")
+                .long("multiline-dotall")
+                .takes_value(false),
+        )
+        .arg(
+            //there is no short flag, as -r is taken by --recursive
+            Arg::with_name("replace")
+                .value_name("TEMPLATE")
+                .help("Print TEMPLATE in place of every match, where $1 and ${name} are groups")
+                .long("replace")
+                .conflicts_with("json"),
+        )
+        .arg(
+            Arg::with_name("search_zip")
+                .help("Search the contents of gzip, bzip2, xz and zstd files")
+                .short("z")
+                .long("search-zip")
+                .takes_value(false),
+        )
+        .arg(
+            Arg::with_name("encoding")
+                .value_name("LABEL")
+                .help("Decode the input from LABEL, such as latin1 or shift_jis")
+                .short("E")
+                .long("encoding"),
+        )
+        .arg(
+            Arg::with_name("regexp")
+                .value_name("PATTERN")
+                .help("Use PATTERN for matching, can be repeated")
+                .short("e")
+                .long("regexp")
+                .multiple(true)
+                .number_of_values(1),
+        )
+        .arg(
+            Arg::with_name("fixed_strings")
+                .help("Interpret the patterns as fixed strings")
+                .short("F")
+                .long("fixed-strings")
+                .takes_value(false),
+        )
+        .arg(
+            Arg::with_name("pattern_file")
+                .value_name("FILE")
+                .help("Take patterns from FILE, one per line")
+                .short("f")
+                .long("file")
+                .multiple(true)
+                .number_of_values(1),
+        )
+        .get_matches_safe()
+        //usage errors go back to main to exit with 2, help and version still exit right here
+        .inspect_err(|e| {
+            if !e.use_stderr() {
+                e.exit();
+            }
+        })?;
+
+    let mut files = matches.values_of_lossy("files").unwrap();
+    let mut patterns = matches.values_of_lossy("regexp").unwrap_or_default();
+    for filename in matches.values_of_lossy("pattern_file").unwrap_or_default() {
+        let file = open(Path::new(&filename)).map_err(|e| format!("{}: {}", filename, e))?;
+        for line in file.lines() {
+            patterns.push(line.map_err(|e| format!("{}: {}", filename, e))?);
+        }
+    }
 
-    let pattern = matches.value_of("pattern").unwrap();
-    let pattern = RegexBuilder::new(pattern)
+    //with -e or -f, the first positional argument is a file rather than the pattern
+    let positional = matches.value_of_lossy("pattern").map(|p| p.to_string());
+    let mut given = matches.occurrences_of("files") > 0;
+    if matches.is_present("regexp") || matches.is_present("pattern_file") {
+        if let Some(filename) = positional {
+            if !given {
+                files.clear();
+            }
+            files.insert(0, filename);
+            given = true;
+        }
+    } else {
+        patterns.extend(positional);
+    }
+    //like GNU grep, -r without any files searches the current directory, not stdin
+    if matches.is_present("recursive") && !given {
+        files = vec![".".to_string()];
+    }
+    let matcher = MatcherBuilder::new()
         .case_insensitive(matches.is_present("insensitive"))
-        .build()
-        .map_err(|_| format!("Invalid pattern \"{}\"", pattern))?;
+        .fixed_strings(matches.is_present("fixed_strings"))
+        .multiline_dotall(matches.is_present("multiline_dotall"))
+        .word_regexp(matches.is_present("word_regexp"))
+        .line_regexp(matches.is_present("line_regexp"))
+        .build(&patterns)?;
+
+    //-C sets both sides, but an explicit -A or -B wins over it
+    let context = parse_context(&matches, "context")?.unwrap_or(0);
+    let after_context = parse_context(&matches, "after_context")?.unwrap_or(context);
+    let before_context = parse_context(&matches, "before_context")?.unwrap_or(context);
+
+    //colors are off unless asked for, and auto only colors a terminal
+    let use_color = match matches.value_of("color") {
+        Some("always") => true,
+        Some("never") => false,
+        _ if matches.is_present("color") => io::stdout().is_terminal(),
+        _ => false,
+    };
+    let colors = if use_color {
+        Some(parse_colors(&env::var("GREPR_COLORS").unwrap_or_default()))
+    } else {
+        None
+    };
+
+    //globs are checked up front, so that a bad one fails before any searching
+    let globs = matches.values_of_lossy("glob").unwrap_or_default();
+    build_overrides(".", &globs)?;
+
+    let threads = match matches.value_of("threads") {
+        Some(val) => match val.parse() {
+            Ok(n) if n > 0 => n,
+            _ => return Err(From::from(format!("Invalid thread count \"{}\"", val))),
+        },
+        None => thread::available_parallelism().map_or(1, |n| n.get()),
+    };
+
+    let max_count = match matches.value_of("max_count") {
+        Some(val) => match val.parse() {
+            Ok(n) => Some(n),
+            _ => return Err(From::from(format!("Invalid max count \"{}\"", val))),
+        },
+        None => None,
+    };
+
+    let encoding = match matches.value_of("encoding") {
+        Some(label) => match Encoding::for_label(label.as_bytes()) {
+            Some(encoding) => Some(encoding),
+            None => return Err(From::from(format!("Invalid encoding \"{}\"", label))),
+        },
+        None => None,
+    };
+
+    let mut searcher = Searcher::new(matcher);
+    searcher
+        .invert_match(matches.is_present("invert"))
+        .before_context(before_context)
+        .after_context(after_context)
+        .max_count(max_count)
+        .binary_files(match matches.value_of("binary_files") {
+            _ if matches.is_present("text") => BinaryFiles::Text,
+            Some("text") => BinaryFiles::Text,
+            Some("without-match") => BinaryFiles::WithoutMatch,
+            _ => BinaryFiles::Binary,
+        })
+        .multiline(matches.is_present("multiline"))
+        .search_zip(matches.is_present("search_zip"))
+        .encoding(encoding);
 
     Ok(Config {
-        pattern,
-        files: matches.values_of_lossy("files").unwrap(),
+        searcher,
+        files,
         recursive: matches.is_present("recursive"),
         count: matches.is_present("count"),
-        invert_match: matches.is_present("invert"),
+        only_matching: matches.is_present("only_matching"),
+        colors,
+        json: matches.is_present("json"),
+        walk: WalkOptions {
+            hidden: matches.is_present("hidden"),
+            no_ignore: matches.is_present("no_ignore"),
+            globs,
+        },
+        threads,
+        unordered: matches.is_present("unordered"),
+        line_number: matches.is_present("line_number"),
+        byte_offset: matches.is_present("byte_offset"),
+        with_filename: if matches.is_present("with_filename") {
+            Some(true)
+        } else if matches.is_present("no_filename") {
+            Some(false)
+        } else {
+            None
+        },
+        null: matches.is_present("null"),
+        list_files: if matches.is_present("files_with_matches") {
+            Some(ListFiles::WithMatches)
+        } else if matches.is_present("files_without_match") {
+            Some(ListFiles::WithoutMatch)
+        } else {
+            None
+        },
+        quiet: matches.is_present("quiet"),
+        replace: matches.value_of_lossy("replace").map(|r| r.to_string()),
+        count_matches: matches.is_present("count_matches"),
+        stats: matches.is_present("stats"),
+    })
+}
+
+//combines the patterns into a single matcher, so that each line is only scanned once
+//every pattern is checked on its own first, so that an error can name the bad one
+//^ and $ always match at line boundaries and '.' only matches a newline with dotall,
+//which only makes a difference in multiline mode, as lines are matched without their newline
+fn build_pattern(
+    patterns: &[String],
+    insensitive: bool,
+    fixed: bool,
+    dotall: bool,
+    boundary: Option<Boundary>,
+) -> MyResult<Matcher> {
+    if fixed {
+        //the automaton only folds ASCII case and knows nothing of words or lines,
+        //so other literals are escaped and left to the regex engine instead
+        if boundary.is_none() && (!insensitive || patterns.iter().all(|p| p.is_ascii())) {
+            return Ok(Matcher(Engine::Literals(Box::new(
+                AhoCorasickBuilder::new()
+                    .ascii_case_insensitive(insensitive)
+                    .match_kind(MatchKind::LeftmostLongest)
+                    .build(patterns),
+            ))));
+        }
+        //longest first, so that the regex prefers them like the automaton does
+        let mut escaped: Vec<String> = patterns.iter().map(|p| regex::escape(p)).collect();
+        escaped.sort_by_key(|p| std::cmp::Reverse(p.len()));
+        return build_pattern(&escaped, insensitive, false, dotall, boundary);
+    }
+
+    for pattern in patterns {
+        Regex::new(pattern).map_err(|_| format!("Invalid pattern \"{}\"", pattern))?;
+    }
+
+    let combined = if patterns.is_empty() {
+        //an empty list of patterns (e.g. an empty -f file) matches nothing
+        r"\b\B".to_string()
+    } else {
+        patterns
+            .iter()
+            .map(|p| format!("(?:{})", p))
+            .collect::<Vec<_>>()
+            .join("|")
+    };
+
+    let build = |pattern: &str| {
+        RegexBuilder::new(pattern)
+            .case_insensitive(insensitive)
+            .multi_line(true)
+            .dot_matches_new_line(dotall)
+            .build()
+    };
+    Ok(Matcher(match boundary {
+        None => Engine::Regex(build(&combined)?),
+        Some(Boundary::Line) => Engine::Regex(build(&format!("^(?:{})$", combined))?),
+        Some(Boundary::Word) => Engine::Words {
+            re: build(&combined)?,
+            whole: build(&format!(r"\A(?:{})\z", combined))?,
+        },
+    }))
+}
+
+//parses a GREP_COLORS style spec such as "ms=01;32:fn=34"
+//mt (or ms) colors matches, fn filenames, ln line numbers, bn byte offsets
+//and se separators
+//unknown keys are ignored, like GNU grep does
+fn parse_colors(spec: &str) -> Colors {
+    let mut colors = Colors::default();
+
+    for entry in spec.split(':') {
+        if let Some((key, value)) = entry.split_once('=') {
+            match key {
+                "mt" | "ms" => colors.matched = value.to_string(),
+                "fn" => colors.filename = value.to_string(),
+                "ln" => colors.line_number = value.to_string(),
+                "bn" => colors.byte_offset = value.to_string(),
+                "se" => colors.separator = value.to_string(),
+                _ => {}
+            }
+        }
+    }
+
+    colors
+}
+
+//parses the value of one of the context options into a number of lines
+fn parse_context(matches: &ArgMatches, name: &str) -> MyResult<Option<usize>> {
+    matches
+        .value_of(name)
+        .map(|val| {
+            val.parse()
+                .map_err(|_| From::from(format!("Invalid context length \"{}\"", val)))
+        })
+        .transpose()
+}
+
+//returns the exit status: 0 when a line was selected, 1 when none was,
+//and 2 when an input could not be searched, unless -q already found a match
+pub fn run(mut config: Config) -> MyResult<i32> {
+    let start = Instant::now();
+    //raised by -q at the first match, so that the searches still going give up
+    let stop = Arc::new(AtomicBool::new(false));
+    config.searcher.stop_flag(Arc::clone(&stop));
+    let config = &config;
+
+    //unless -H or -h say otherwise, only prefix the filename when there is
+    //more than one input, or when a directory is being searched recursively
+    let many = config.files.len() > 1
+        || (config.recursive && config.files.iter().any(|f| Path::new(f).is_dir()));
+    let show_filename = config.with_filename.unwrap_or(many);
+    let stdout = io::stdout();
+    let mut out = Grouped::new(stdout.lock(), config);
+    let mut total = Stats::default();
+
+    //a single input, or a single thread, needs no workers, and its output can go
+    //straight to stdout as it is found
+    let closed = if config.threads == 1 || !many {
+        let mut closed = false;
+        find_files(&config.files, config.recursive, &config.walk, |entry| {
+            let searched = match entry {
+                Err(e) => Searched::failed(e.to_string()),
+                Ok(path) => search_file(&path, config, show_filename, &mut out),
+            };
+            if searched.closed {
+                closed = true;
+                return false;
+            }
+            searched.finish(&mut total);
+            //-q is done with the first match
+            !(config.quiet && total.searches_with_match > 0)
+        });
+        closed
+    } else {
+        let mut writer = Writer::new(&mut out, config.unordered);
+        let closed = search_parallel(config, show_filename, &stop, &mut writer)?;
+        total = writer.total;
+        closed
+    };
+    //whatever reads the output went away, as with | head, so there is
+    //nothing left to do, and nothing went wrong either
+    if closed {
+        return Ok(0);
+    }
+
+    if config.json && !config.quiet {
+        json_summary(&mut out, &total, start.elapsed())?;
+    }
+    if config.stats {
+        write_stats(&mut io::stderr(), &total, start.elapsed())?;
+    }
+
+    Ok(if config.quiet && total.searches_with_match > 0 {
+        0
+    } else if total.errors > 0 {
+        2
+    } else if total.searches_with_match > 0 {
+        0
+    } else {
+        1
     })
 }
 
-pub fn run(config: Config) -> MyResult<()> {
-    println!("{:#?}", config);
+//one thread walks the inputs, a pool of workers searches them, and this thread
+//writes the results, in walk order unless --unordered
+//returns whether it stopped because stdout was closed
+fn search_parallel<W: Output>(
+    config: &Config,
+    show_filename: bool,
+    stop: &AtomicBool,
+    writer: &mut Writer<W>,
+) -> MyResult<bool> {
+    thread::scope(|scope| -> MyResult<bool> {
+        let (job_tx, job_rx) =
+            mpsc::sync_channel::<(usize, Result<PathBuf, String>)>(config.threads * 4);
+        let (event_tx, event_rx) = mpsc::channel::<(usize, Event)>();
+
+        scope.spawn(move || {
+            let mut index = 0;
+            find_files(&config.files, config.recursive, &config.walk, |entry| {
+                //the send only fails once the workers are gone, and then there is
+                //no point in walking any further
+                let sent = job_tx.send((index, entry.map_err(|e| e.to_string())));
+                index += 1;
+                sent.is_ok() && !stop.load(Ordering::Relaxed)
+            });
+        });
+
+        //the workers share the receiving end, which is dropped once they have all
+        //finished, so the walker can't get stuck on a full channel
+        let job_rx = Arc::new(Mutex::new(job_rx));
+        for _ in 0..config.threads {
+            let job_rx = Arc::clone(&job_rx);
+            let event_tx = event_tx.clone();
+            scope.spawn(move || loop {
+                let job = job_rx.lock().unwrap().recv();
+                let Ok((index, entry)) = job else {
+                    break;
+                };
+                if stop.load(Ordering::Relaxed) {
+                    break;
+                }
+                let searched = match entry {
+                    Err(e) => Searched::failed(e),
+                    Ok(path) => {
+                        let mut out = Chunks::new(index, &event_tx);
+                        let searched = search_file(&path, config, show_filename, &mut out);
+                        //what is left after the last newline
+                        let _ = out.flush();
+                        searched
+                    }
+                };
+                if event_tx.send((index, Event::Done(searched))).is_err() {
+                    break;
+                }
+            });
+        }
+        drop(job_rx);
+        drop(event_tx);
+
+        for (index, event) in event_rx {
+            //-q is done with the first match, the flag makes the workers and the
+            //walker stop too, without waiting for the inputs they are in the middle of
+            if let Event::Done(searched) = &event {
+                if config.quiet && searched.stats.searches_with_match > 0 {
+                    stop.store(true, Ordering::Relaxed);
+                    writer.total.add(&searched.stats);
+                    break;
+                }
+            }
+            match writer.send(index, event) {
+                Err(e) if broken_pipe(&*e) => {
+                    stop.store(true, Ordering::Relaxed);
+                    return Ok(true);
+                }
+                res => res?,
+            }
+        }
+        Ok(false)
+    })
+}
+
+//what searching one input ended with
+#[derive(Debug, Default)]
+struct Searched {
+    error: Option<String>,
+    stats: Stats,
+    //stdout was closed, so there is no point in searching anything else
+    closed: bool,
+}
+
+impl Searched {
+    fn failed(error: String) -> Self {
+        Searched {
+            error: Some(error),
+            ..Searched::default()
+        }
+    }
+
+    //reports the error, if any, and adds up what was seen
+    fn finish(&self, total: &mut Stats) {
+        if let Some(e) = &self.error {
+            eprintln!("{}", e);
+            total.errors += 1;
+        }
+        total.add(&self.stats);
+    }
+}
+
+fn broken_pipe(e: &(dyn Error + 'static)) -> bool {
+    e.downcast_ref::<io::Error>()
+        .is_some_and(|e| e.kind() == io::ErrorKind::BrokenPipe)
+}
+
+//where the output of searching an input goes
+trait Output: Write {
+    //called before the first group of lines of an input, to put a "--" between
+    //the groups of different inputs once their order is known
+    fn begin_group(&mut self) -> MyResult<()>;
+}
+
+impl Output for io::Sink {
+    fn begin_group(&mut self) -> MyResult<()> {
+        Ok(())
+    }
+}
+
+//the inputs written one after another, with a "--" between the groups of lines
+//that come from different inputs
+struct Grouped<'c, W> {
+    out: W,
+    config: &'c Config,
+    wrote_group: bool,
+}
+
+impl<'c, W: Write> Grouped<'c, W> {
+    fn new(out: W, config: &'c Config) -> Self {
+        Grouped {
+            out,
+            config,
+            wrote_group: false,
+        }
+    }
+}
+
+impl<W: Write> Write for Grouped<'_, W> {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        self.out.write(buf)
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        self.out.flush()
+    }
+}
+
+impl<W: Write> Output for Grouped<'_, W> {
+    fn begin_group(&mut self) -> MyResult<()> {
+        if self.wrote_group {
+            Printer::new("", false, self.config).separator(&mut self.out)?;
+        }
+        self.wrote_group = true;
+        Ok(())
+    }
+}
+
+//what a worker sends to the writer about the input it searches
+#[derive(Debug)]
+enum Event {
+    Bytes(Vec<u8>),
+    //a group of lines starts here
+    Group,
+    //nothing comes after this
+    Done(Searched),
+}
+
+//the output of a worker, sent to the writer a line at a time so that it can
+//stream the input at the head of the order
+struct Chunks<'a> {
+    index: usize,
+    tx: &'a mpsc::Sender<(usize, Event)>,
+    buf: Vec<u8>,
+}
+
+impl<'a> Chunks<'a> {
+    fn new(index: usize, tx: &'a mpsc::Sender<(usize, Event)>) -> Self {
+        Chunks {
+            index,
+            tx,
+            buf: vec![],
+        }
+    }
+
+    fn send(&self, event: Event) -> io::Result<()> {
+        self.tx
+            .send((self.index, event))
+            .map_err(|_| io::Error::new(io::ErrorKind::BrokenPipe, "the writer is gone"))
+    }
+}
+
+impl Write for Chunks<'_> {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        self.buf.extend_from_slice(buf);
+        if buf.contains(&b'\n') {
+            self.flush()?;
+        }
+        Ok(buf.len())
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        if !self.buf.is_empty() {
+            let bytes = mem::take(&mut self.buf);
+            self.send(Event::Bytes(bytes))?;
+        }
+        Ok(())
+    }
+}
+
+impl Output for Chunks<'_> {
+    fn begin_group(&mut self) -> MyResult<()> {
+        self.flush()?;
+        Ok(self.send(Event::Group)?)
+    }
+}
+
+//writes what the workers send, one input at a time: the one at the head streams
+//straight through, and the others wait in pending until it is their turn
+//in walk order the head is the next input, with --unordered it is whichever
+//has something to write, the ones that are done first
+struct Writer<'o, W> {
+    out: &'o mut W,
+    unordered: bool,
+    head: Option<usize>,
+    pending: HashMap<usize, Vec<Event>>,
+    total: Stats,
+}
+
+impl<'o, W: Output> Writer<'o, W> {
+    fn new(out: &'o mut W, unordered: bool) -> Self {
+        Writer {
+            out,
+            unordered,
+            head: if unordered { None } else { Some(0) },
+            pending: HashMap::new(),
+            total: Stats::default(),
+        }
+    }
+
+    fn send(&mut self, index: usize, event: Event) -> MyResult<()> {
+        let head = *self.head.get_or_insert(index);
+        if index != head {
+            self.pending.entry(index).or_default().push(event);
+        } else if self.write(event)? {
+            self.next_head()?;
+        }
+        Ok(())
+    }
+
+    //writes one event of the head, and returns whether the head is done
+    fn write(&mut self, event: Event) -> MyResult<bool> {
+        match event {
+            Event::Bytes(bytes) => self.out.write_all(&bytes)?,
+            Event::Group => self.out.begin_group()?,
+            Event::Done(searched) => {
+                searched.finish(&mut self.total);
+                return Ok(true);
+            }
+        }
+        Ok(false)
+    }
+
+    //moves on from a head that is done, writing what the next ones have so far
+    fn next_head(&mut self) -> MyResult<()> {
+        loop {
+            self.head = if self.unordered {
+                let done = |events: &Vec<Event>| matches!(events.last(), Some(Event::Done(_)));
+                (self.pending.iter().find(|(_, events)| done(events)))
+                    .or_else(|| self.pending.iter().next())
+                    .map(|(index, _)| *index)
+            } else {
+                self.head.map(|head| head + 1)
+            };
+            let Some(events) = self.head.and_then(|head| self.pending.remove(&head)) else {
+                return Ok(());
+            };
+            let mut done = false;
+            for event in events {
+                done = self.write(event)?;
+            }
+            if !done {
+                return Ok(());
+            }
+        }
+    }
+}
+
+//searches one input, writing what it finds to out
+fn search_file<O: Output>(
+    path: &Path,
+    config: &Config,
+    show_filename: bool,
+    out: &mut O,
+) -> Searched {
+    let filename = path.to_string_lossy();
+    let printer = Printer::new(path, show_filename, config);
+    let mut searched = Searched::default();
+
+    let file = match config.searcher.open(path) {
+        Err(e) => return Searched::failed(format!("{}: {}", filename, e)),
+        Ok(file) => file,
+    };
+    let start = Instant::now();
+    //-q prints nothing at all
+    let mut sink = io::sink();
+    let out: &mut dyn Output = if config.quiet { &mut sink } else { out };
+    let res = printer.begin(out).and_then(|_| {
+        let stats = grep_lines(file, &printer, config, out)?;
+        match &config.list_files {
+            Some(list) if (stats.matched_lines > 0) == (*list == ListFiles::WithMatches) => {
+                printer.path(out)?
+            }
+            Some(_) => {}
+            //an inverted search has no matches to count, only lines
+            None if config.count_matches && !config.searcher.invert_match => {
+                printer.count(out, stats.matches)?
+            }
+            None if config.count || config.count_matches => {
+                printer.count(out, stats.matched_lines)?
+            }
+            None => {}
+        }
+        printer.end(out, &stats, start.elapsed())?;
+        Ok(stats)
+    });
+
+    match res {
+        Ok(stats) => searched.stats = stats,
+        Err(e) if broken_pipe(&*e) => searched.closed = true,
+        Err(e) => searched.error = Some(format!("{}: {}", filename, e)),
+    }
+    searched
+}
+
+//writes the summary for --stats, one number per line
+fn write_stats<W: Write>(out: &mut W, stats: &Stats, elapsed: Duration) -> MyResult<()> {
+    writeln!(out, "{} files searched", stats.searches)?;
+    writeln!(out, "{} files matched", stats.searches_with_match)?;
+    writeln!(out, "{} lines searched", stats.lines_searched)?;
+    writeln!(out, "{} bytes searched", stats.bytes_searched)?;
+    writeln!(out, "{} matched lines", stats.matched_lines)?;
+    writeln!(out, "{} matches", stats.matches)?;
+    writeln!(out, "{:.6} seconds", elapsed.as_secs_f64())?;
+    Ok(())
+}
+
+//expands the input paths into the files to search, passing each one to found
+//directories are only walked when recursive, otherwise they are reported as errors
+//found returns false to stop looking for more
+//the paths found are kept as the OS gave them, as they may not be valid UTF-8
+fn find_files(
+    paths: &[String],
+    recursive: bool,
+    walk: &WalkOptions,
+    mut found: impl FnMut(MyResult<PathBuf>) -> bool,
+) {
+    for path in paths {
+        let keep_going = match path.as_str() {
+            "-" => found(Ok(PathBuf::from(path))),
+            _ => match fs::metadata(path) {
+                Err(e) => found(Err(From::from(format!("{}: {}", path, e)))),
+                Ok(metadata) if metadata.is_dir() && recursive => match walk_dir(path, walk) {
+                    Ok(walker) => walker
+                        .filter_map(|entry| match entry {
+                            Err(e) => Some(Err(From::from(e.to_string()))),
+                            //only regular files are searched,
+                            //symlinks found while walking are skipped
+                            Ok(entry) if entry.file_type().is_some_and(|t| t.is_file()) => {
+                                Some(Ok(entry.into_path()))
+                            }
+                            _ => None,
+                        })
+                        .all(&mut found),
+                    Err(e) => found(Err(e)),
+                },
+                Ok(metadata) if metadata.is_dir() => {
+                    found(Err(From::from(format!("{} is a directory", path))))
+                }
+                Ok(_) => found(Ok(PathBuf::from(path))),
+            },
+        };
+        if !keep_going {
+            return;
+        }
+    }
+}
+
+//builds the walker for one directory, which skips hidden entries and anything
+//listed in .gitignore, .ignore or the global git excludes file, like ripgrep does
+//unlike ripgrep, .gitignore files are honoured even outside of a git repository
+fn walk_dir(path: &str, walk: &WalkOptions) -> MyResult<Walk> {
+    Ok(WalkBuilder::new(path)
+        .standard_filters(!walk.no_ignore)
+        .hidden(!walk.hidden)
+        .require_git(false)
+        .overrides(build_overrides(path, &walk.globs)?)
+        .build())
+}
+
+//turns the --glob values into include/exclude rules relative to root
+fn build_overrides(root: &str, globs: &[String]) -> MyResult<Override> {
+    let mut overrides = OverrideBuilder::new(root);
+    for glob in globs {
+        overrides
+            .add(glob)
+            .map_err(|_| format!("Invalid --glob \"{}\"", glob))?;
+    }
+    Ok(overrides.build()?)
+}
+
+//what was seen while searching one or more files
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct Stats {
+    pub searches: usize,
+    pub searches_with_match: usize,
+    pub bytes_searched: usize,
+    pub lines_searched: usize,
+    pub matched_lines: usize,
+    //the number of non-empty matches inside the matched lines
+    pub matches: usize,
+    //inputs that could not be searched
+    pub errors: usize,
+}
+
+impl Stats {
+    fn add(&mut self, other: &Stats) {
+        self.searches += other.searches;
+        self.searches_with_match += other.searches_with_match;
+        self.bytes_searched += other.bytes_searched;
+        self.lines_searched += other.lines_searched;
+        self.matched_lines += other.matched_lines;
+        self.matches += other.matches;
+        self.errors += other.errors;
+    }
+}
+
+//searches inputs for the lines a Matcher selects, along with the context around them
+//the options are the ones of -v, -B, -A, -m, --binary-files, -U and -z
+#[derive(Debug)]
+pub struct Searcher {
+    matcher: Matcher,
+    invert_match: bool,
+    before_context: usize,
+    after_context: usize,
+    //stop each input after this many selected lines
+    max_count: Option<usize>,
+    binary_files: BinaryFiles,
+    //match against the whole input, so that matches can span lines
+    multiline: bool,
+    //decompress gzip, bzip2, xz and zstd inputs before searching them
+    search_zip: bool,
+    //what the input is decoded from, instead of looking for a UTF-16 BOM
+    encoding: Option<&'static Encoding>,
+    //once set, every search stops reading as if its input had ended
+    stop: Option<Arc<AtomicBool>>,
+}
+
+impl Searcher {
+    pub fn new(matcher: Matcher) -> Self {
+        Searcher {
+            matcher,
+            invert_match: false,
+            before_context: 0,
+            after_context: 0,
+            max_count: None,
+            binary_files: BinaryFiles::Binary,
+            multiline: false,
+            search_zip: false,
+            encoding: None,
+            stop: None,
+        }
+    }
+
+    //select the lines that don't match instead
+    pub fn invert_match(&mut self, yes: bool) -> &mut Self {
+        self.invert_match = yes;
+        self
+    }
+
+    pub fn before_context(&mut self, lines: usize) -> &mut Self {
+        self.before_context = lines;
+        self
+    }
+
+    pub fn after_context(&mut self, lines: usize) -> &mut Self {
+        self.after_context = lines;
+        self
+    }
+
+    pub fn max_count(&mut self, max: Option<usize>) -> &mut Self {
+        self.max_count = max;
+        self
+    }
+
+    pub fn binary_files(&mut self, binary_files: BinaryFiles) -> &mut Self {
+        self.binary_files = binary_files;
+        self
+    }
+
+    pub fn multiline(&mut self, yes: bool) -> &mut Self {
+        self.multiline = yes;
+        self
+    }
+
+    //only applies to search_path, a reader is searched as it is
+    pub fn search_zip(&mut self, yes: bool) -> &mut Self {
+        self.search_zip = yes;
+        self
+    }
+
+    //the input is transcoded to UTF-8 before it is searched, so the line numbers,
+    //offsets and spans of the records all point into the decoded text
+    pub fn encoding(&mut self, encoding: Option<&'static Encoding>) -> &mut Self {
+        self.encoding = encoding;
+        self
+    }
+
+    //lets another thread cut the searches short, e.g. when any match will do
+    pub fn stop_flag(&mut self, stop: Arc<AtomicBool>) -> &mut Self {
+        self.stop = Some(stop);
+        self
+    }
+
+    fn stopped(&self) -> bool {
+        self.stop
+            .as_ref()
+            .is_some_and(|stop| stop.load(Ordering::Relaxed))
+    }
+
+    //searches what the reader gives, path only ends up in the records
+    pub fn search_reader<R: Read>(&self, path: &str, reader: R) -> MyResult<Search<'_, R>> {
+        self.search(path, reader)
+    }
+
+    //searches a file, or stdin when the path is "-"
+    pub fn search_path<P: AsRef<Path>>(&self, path: P) -> MyResult<Search<'_, Box<dyn BufRead>>> {
+        let path = path.as_ref();
+        self.search(&path.to_string_lossy(), self.open(path)?)
+    }
+
+    //hands every record to the sink until it asks to stop, and returns what was seen
+    pub fn search_sink<R: Read, S: Sink>(
+        &self,
+        path: &str,
+        reader: R,
+        sink: &mut S,
+    ) -> MyResult<Stats> {
+        let mut search = self.search_reader(path, reader)?;
+        for record in &mut search {
+            if !sink.record(&record?)? {
+                break;
+            }
+        }
+        Ok(search.stats)
+    }
+
+    fn open(&self, path: &Path) -> MyResult<Box<dyn BufRead>> {
+        let file = open(path)?;
+        if self.search_zip {
+            decompress(file)
+        } else {
+            Ok(file)
+        }
+    }
+
+    //without an encoding, an input that starts with a UTF-16 BOM is transcoded,
+    //anything else is searched as the bytes it is made of
+    //a NUL byte in the first block read is taken as the sign of a binary input,
+    //which is only checked after decoding, as UTF-16 text is full of them
+    fn search<R: Read>(&self, path: &str, reader: R) -> MyResult<Search<'_, R>> {
+        let mut decoder = DecodeReaderBytesBuilder::new()
+            .encoding(self.encoding)
+            .utf8_passthru(true)
+            .strip_bom(true)
+            .build(reader);
+        let head = read_head(&mut decoder)?;
+        let binary = self.binary_files != BinaryFiles::Text && head.contains(&0);
+        let reader = BufReader::new(Cursor::new(head).chain(decoder));
+        Ok(Search {
+            searcher: self,
+            path: path.to_string(),
+            reader,
+            binary,
+            stats: Stats {
+                searches: 1,
+                ..Stats::default()
+            },
+            done: binary && self.binary_files == BinaryFiles::WithoutMatch,
+            pending: VecDeque::new(),
+            before: VecDeque::with_capacity(self.before_context),
+            after_left: 0,
+            line: vec![],
+        })
+    }
+}
+
+//the first block of the input, where a binary one should show a NUL byte
+//the decoder hands out the bytes it looks at for a BOM on their own,
+//so reading goes on until there is more than that to look at
+fn read_head<R: Read>(reader: &mut R) -> io::Result<Vec<u8>> {
+    let mut head = vec![0; 8 * 1024];
+    let mut len = 0;
+    while len <= 3 {
+        match reader.read(&mut head[len..])? {
+            0 => break,
+            n => len += n,
+        }
+    }
+    head.truncate(len);
+    Ok(head)
+}
+
+//in multiline mode, a selected record holds every line its matches touch
+#[derive(Debug, PartialEq)]
+pub struct Record {
+    pub path: String,
+    //the number of the first line in the record, counting from 1
+    pub line_number: usize,
+    //where the record starts in the input, in bytes
+    pub offset: usize,
+    //the lines as they were read, line endings included
+    pub bytes: Vec<u8>,
+    //the byte ranges of the non-empty matches in bytes, which never take in the last
+    //line ending, and are left empty for context and when inverting
+    pub spans: Vec<Range<usize>>,
+    pub context: bool,
+}
+
+impl Record {
+    //the bytes without the line ending at the end, if there is one
+    pub fn text(&self) -> &[u8] {
+        self.bytes.strip_suffix(b"\n").unwrap_or(&self.bytes)
+    }
+
+    //the number of the last line in the record
+    pub fn last_line_number(&self) -> usize {
+        self.line_number + self.text().iter().filter(|&&b| b == b'\n').count()
+    }
+}
+
+//receives the records of a search, and returns false to stop it
+pub trait Sink {
+    fn record(&mut self, record: &Record) -> MyResult<bool>;
+}
+
+impl<F: FnMut(&Record) -> MyResult<bool>> Sink for F {
+    fn record(&mut self, record: &Record) -> MyResult<bool> {
+        self(record)
+    }
+}
+
+//an input transcoded to UTF-8, with its first block read ahead
+type Decoded<R> = io::Chain<Cursor<Vec<u8>>, DecodeReaderBytes<R, Vec<u8>>>;
+
+//the records of one input, read as they are asked for
+pub struct Search<'s, R> {
+    searcher: &'s Searcher,
+    path: String,
+    reader: BufReader<Decoded<R>>,
+    binary: bool,
+    stats: Stats,
+    //set once nothing more is to be read
+    done: bool,
+    //records found but not handed out yet
+    pending: VecDeque<Record>,
+    //the most recent lines not handed out, bounded by the leading context
+    before: VecDeque<Record>,
+    //lines still owed to the trailing context of the last match
+    after_left: usize,
+    //the line being read, kept to reuse its buffer
+    line: Vec<u8>,
+}
+
+impl<R: Read> Search<'_, R> {
+    //whether the input looks binary, which is never the case with BinaryFiles::Text
+    pub fn binary(&self) -> bool {
+        self.binary
+    }
+
+    //what was seen so far
+    pub fn stats(&self) -> &Stats {
+        &self.stats
+    }
+
+    fn record(
+        &self,
+        line_number: usize,
+        offset: usize,
+        bytes: &[u8],
+        spans: Vec<Range<usize>>,
+        context: bool,
+    ) -> Record {
+        Record {
+            path: self.path.clone(),
+            line_number,
+            offset,
+            bytes: bytes.to_vec(),
+            spans,
+            context,
+        }
+    }
+
+    //reads one more line, and queues the records that it completes
+    fn read_line(&mut self) -> MyResult<()> {
+        let searcher = self.searcher;
+        //past -m, only the trailing context of the last match is still read
+        let reached_max = searcher
+            .max_count
+            .is_some_and(|max| self.stats.matched_lines >= max);
+        if reached_max && self.after_left == 0 {
+            self.done = true;
+            return Ok(());
+        }
+
+        self.line.clear();
+        let offset = self.stats.bytes_searched;
+        let bytes = self.reader.read_until(b'\n', &mut self.line)?;
+        if bytes == 0 {
+            self.done = true;
+            return Ok(());
+        }
+        self.stats.lines_searched += 1;
+        self.stats.bytes_searched += bytes;
+        let line_number = self.stats.lines_searched;
+
+        //the line ending is kept in the record, but must not take part in the match
+        //XOR: keep a matching line, or a non-matching one when inverting
+        let text = self.line.strip_suffix(b"\n").unwrap_or(&self.line);
+        if !reached_max && searcher.matcher.is_match(text) ^ searcher.invert_match {
+            //an inverted match has nothing to point at
+            let spans: Vec<_> = if searcher.invert_match {
+                vec![]
+            } else {
+                searcher.matcher.find_iter(text).collect()
+            };
+            self.stats.matched_lines += 1;
+            self.stats.matches += spans.len();
+            self.stats.searches_with_match = 1;
+            let record = self.record(line_number, offset, &self.line, spans, false);
+            self.pending.extend(self.before.drain(..));
+            self.pending.push_back(record);
+            self.after_left = searcher.after_context;
+        } else if self.after_left > 0 {
+            let record = self.record(line_number, offset, &self.line, vec![], true);
+            self.pending.push_back(record);
+            self.after_left -= 1;
+        } else if searcher.before_context > 0 {
+            if self.before.len() == searcher.before_context {
+                self.before.pop_front();
+            }
+            let record = self.record(line_number, offset, &self.line, vec![], true);
+            self.before.push_back(record);
+        }
+        Ok(())
+    }
+
+    //like read_line, but the pattern runs over the whole input at once, so that a match
+    //can span lines, and the lines a match touches are selected together as one block
+    fn read_all(&mut self) -> MyResult<()> {
+        let searcher = self.searcher;
+        self.done = true;
+        let mut buf = vec![];
+        self.reader.read_to_end(&mut buf)?;
+        self.stats.bytes_searched = buf.len();
+
+        //the byte range of every line, along with its newline
+        let mut lines = vec![];
+        let mut start = 0;
+        for (i, _) in buf.iter().enumerate().filter(|(_, &b)| b == b'\n') {
+            lines.push(start..i + 1);
+            start = i + 1;
+        }
+        if start < buf.len() {
+            lines.push(start..buf.len());
+        }
+        self.stats.lines_searched = lines.len();
+        let line_of = |offset: usize| lines.partition_point(|line| line.end <= offset);
+
+        //the first and last line of each block and the matches in it,
+        //where matches that share a line end up in the same block
+        let mut blocks: Vec<(usize, usize, Vec<Range<usize>>)> = vec![];
+        for span in searcher.matcher.spans(&buf) {
+            let first = line_of(span.start);
+            //an empty match at the very end has no line to be on
+            if first == lines.len() {
+                continue;
+            }
+            let last = line_of(span.end.max(span.start + 1) - 1);
+            match blocks.last_mut() {
+                Some(block) if first <= block.1 => {
+                    block.1 = block.1.max(last);
+                    block.2.push(span);
+                }
+                _ => blocks.push((first, last, vec![span])),
+            }
+        }
+
+        //inverting selects the lines no match touched, one at a time
+        if searcher.invert_match {
+            let mut covered = blocks
+                .iter()
+                .map(|(first, last, _)| *first..=*last)
+                .peekable();
+            let mut selected = vec![];
+            for num in 0..lines.len() {
+                while covered.next_if(|range| *range.end() < num).is_some() {}
+                if !covered.peek().is_some_and(|range| range.contains(&num)) {
+                    selected.push((num, num, vec![]));
+                }
+            }
+            blocks = selected;
+        }
+        if let Some(max) = searcher.max_count {
+            blocks.truncate(max);
+        }
+
+        //a record of the lines from first to last
+        let record = |first: usize, last: usize, spans, context| {
+            let range = lines[first].start..lines[last].end;
+            self.record(first + 1, range.start, &buf[range], spans, context)
+        };
+        let mut records = vec![];
+        let mut matches = 0;
+        //index of the last line queued, so that context isn't queued twice
+        let mut last_queued: Option<usize> = None;
+        for (i, (first, last, spans)) in blocks.iter().enumerate() {
+            let (first, last) = (*first, *last);
+            let from = first
+                .saturating_sub(searcher.before_context)
+                .max(last_queued.map_or(0, |num| num + 1));
+            for num in from..first {
+                records.push(record(num, num, vec![], true));
+            }
+
+            //the spans are made relative to the block, short of its last newline
+            let start = lines[first].start;
+            let end = lines[last].end - usize::from(buf[lines[last].end - 1] == b'\n');
+            let spans: Vec<_> = spans
+                .iter()
+                .map(|span| span.start - start..span.end.min(end) - start)
+                .filter(|span| !span.is_empty())
+                .collect();
+            matches += spans.len();
+            records.push(record(first, last, spans, false));
+
+            //the trailing context stops where the next block starts
+            let next = blocks.get(i + 1).map_or(lines.len(), |block| block.0);
+            let to = (last + 1 + searcher.after_context).min(next);
+            for num in last + 1..to {
+                records.push(record(num, num, vec![], true));
+            }
+            last_queued = Some(to.max(last + 1) - 1);
+        }
+
+        self.stats.matched_lines = blocks.iter().map(|(first, last, _)| last - first + 1).sum();
+        self.stats.matches = matches;
+        if !blocks.is_empty() {
+            self.stats.searches_with_match = 1;
+        }
+        self.pending.extend(records);
+        Ok(())
+    }
+}
+
+impl<R: Read> Iterator for Search<'_, R> {
+    type Item = MyResult<Record>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        while self.pending.is_empty() && !self.done {
+            if self.searcher.stopped() {
+                self.done = true;
+                break;
+            }
+            let read = if self.searcher.multiline {
+                self.read_all()
+            } else {
+                self.read_line()
+            };
+            if let Err(e) = read {
+                self.done = true;
+                return Some(Err(e));
+            }
+        }
+        self.pending.pop_front().map(Ok)
+    }
+}
+
+//writes the lines of a file that match (or don't match, when inverting) the pattern,
+//along with any requested context, and returns what was seen
+//groups of lines that are not adjacent get a "--" line in between,
+//and out is told about the first one to tell it apart from the inputs before
+fn grep_lines<T: BufRead, W: Output + ?Sized>(
+    file: T,
+    printer: &Printer,
+    config: &Config,
+    out: &mut W,
+) -> MyResult<Stats> {
+    let searcher = &config.searcher;
+    let mut search = searcher.search(&printer.path, file)?;
+    let binary = search.binary();
+    let counting = config.count || config.count_matches;
+    //only the matched parts are printed with -o, so there is no context around them
+    let context =
+        !config.only_matching && (searcher.before_context > 0 || searcher.after_context > 0);
+
+    //line number of the last line written, used to merge overlapping windows
+    let mut last_written: Option<usize> = None;
+    for record in search.by_ref() {
+        let record = record?;
+        //listing files, or -q, only needs to know whether there is a match,
+        //so nothing is written, not even the context queued before it, and
+        //reading stops there
+        if config.list_files.is_some() || config.quiet {
+            if record.context {
+                continue;
+            }
+            break;
+        }
+        if counting || (record.context && (binary || config.only_matching)) {
+            continue;
+        }
+        //the lines of a binary file are never printed, the first match is enough
+        if binary {
+            printer.binary_matches(out)?;
+            break;
+        }
+
+        if context {
+            match last_written {
+                None => out.begin_group()?,
+                Some(last) if record.line_number > last + 1 => printer.separator(out)?,
+                Some(_) => {}
+            }
+        }
+        if record.context {
+            printer.context(out, &record)?;
+        } else if config.only_matching {
+            printer.only_matching(out, &record)?;
+        } else {
+            printer.matched(out, &record)?;
+        }
+        last_written = Some(record.last_line_number());
+    }
+    Ok(search.stats().clone())
+}
+
+//writes the output for one file, either as text or as JSON events
+//lines are written as the bytes they were read as
+struct Printer<'a> {
+    //the path as it is shown, with anything that isn't UTF-8 replaced
+    path: Cow<'a, str>,
+    //the path as the OS gave it, which JSON keeps as it is
+    os_path: &'a Path,
+    //prefix every line of text with the path
+    show_filename: bool,
+    colors: Option<&'a Colors>,
+    //runs again over the selected lines for --replace, to get at the groups
+    matcher: &'a Matcher,
+    json: bool,
+    line_number: bool,
+    byte_offset: bool,
+    //end the filename with a NUL byte rather than a separator
+    null: bool,
+    replace: Option<&'a [u8]>,
+}
+
+impl<'a> Printer<'a> {
+    fn new<P: AsRef<Path> + ?Sized>(path: &'a P, show_filename: bool, config: &'a Config) -> Self {
+        let path = path.as_ref();
+        Printer {
+            path: path.to_string_lossy(),
+            os_path: path,
+            show_filename,
+            colors: config.colors.as_ref(),
+            matcher: &config.searcher.matcher,
+            json: config.json,
+            line_number: config.line_number,
+            byte_offset: config.byte_offset,
+            null: config.null,
+            replace: config.replace.as_ref().map(|r| r.as_bytes()),
+        }
+    }
+
+    //writes text wrapped in an SGR color sequence, when colors are on
+    fn colored<W: Write + ?Sized>(
+        &self,
+        out: &mut W,
+        color: impl Fn(&Colors) -> &str,
+        text: &[u8],
+    ) -> MyResult<()> {
+        match self.colors {
+            Some(colors) => {
+                write!(out, "\x1b[{}m\x1b[K", color(colors))?;
+                out.write_all(text)?;
+                write!(out, "\x1b[m\x1b[K")?;
+            }
+            None => out.write_all(text)?,
+        }
+        Ok(())
+    }
+
+    //writes the filename, line number and byte offset that were asked for,
+    //each followed by sep, which tells matching lines (':') apart from context lines ('-')
+    //position is the line number and offset, when there is a line to point at
+    fn prefix<W: Write + ?Sized>(
+        &self,
+        out: &mut W,
+        sep: char,
+        position: Option<(usize, usize)>,
+    ) -> MyResult<()> {
+        let sep = sep.to_string();
+        if self.show_filename {
+            self.colored(out, |c| &c.filename, self.path.as_bytes())?;
+            if self.null {
+                out.write_all(b"\0")?;
+            } else {
+                self.colored(out, |c| &c.separator, sep.as_bytes())?;
+            }
+        }
+        if let Some((line_num, offset)) = position {
+            if self.line_number {
+                self.colored(out, |c| &c.line_number, line_num.to_string().as_bytes())?;
+                self.colored(out, |c| &c.separator, sep.as_bytes())?;
+            }
+            if self.byte_offset {
+                self.colored(out, |c| &c.byte_offset, offset.to_string().as_bytes())?;
+                self.colored(out, |c| &c.separator, sep.as_bytes())?;
+            }
+        }
+        Ok(())
+    }
+
+    //writes the "--" line between groups of context
+    //JSON events carry line numbers instead, so they don't need one
+    fn separator<W: Write + ?Sized>(&self, out: &mut W) -> MyResult<()> {
+        if !self.json {
+            self.colored(out, |c| &c.separator, b"--")?;
+            writeln!(out)?;
+        }
+        Ok(())
+    }
+
+    //writes a selected line, highlighting its matches
+    fn matched<W: Write + ?Sized>(&self, out: &mut W, record: &Record) -> MyResult<()> {
+        if self.json {
+            return self.json_line(out, "match", record);
+        }
+        self.prefix(out, ':', Some((record.line_number, record.offset)))?;
+
+        //the last line of a file may not end in a newline
+        let text = record.text();
+        if self.colors.is_some() || self.replace.is_some() {
+            let mut last = 0;
+            for (span, matched) in self.matches(text, &record.spans) {
+                out.write_all(&text[last..span.start])?;
+                self.colored_match(out, &matched)?;
+                last = span.end;
+            }
+            out.write_all(&text[last..])?;
+        } else {
+            out.write_all(text)?;
+        }
+        writeln!(out)?;
+        Ok(())
+    }
+
+    //the non-empty matches in the text, each with what is written for it:
+    //the match itself, or its replacement with --replace
+    fn matches<'t>(
+        &self,
+        text: &'t [u8],
+        spans: &[Range<usize>],
+    ) -> Vec<(Range<usize>, Cow<'t, [u8]>)> {
+        match self.replace {
+            Some(template) if !spans.is_empty() => self
+                .matcher
+                .replacements(text, template)
+                .into_iter()
+                .map(|(span, replaced)| (span, Cow::Owned(replaced)))
+                .collect(),
+            _ => spans
+                .iter()
+                .map(|span| (span.clone(), Cow::Borrowed(&text[span.clone()])))
+                .collect(),
+        }
+    }
+
+    //writes a match in its color, line by line, as one can span lines in multiline mode
+    fn colored_match<W: Write + ?Sized>(&self, out: &mut W, matched: &[u8]) -> MyResult<()> {
+        for (i, part) in matched.split(|&b| b == b'\n').enumerate() {
+            if i > 0 {
+                writeln!(out)?;
+            }
+            if !part.is_empty() {
+                self.colored(out, |c| &c.matched, part)?;
+            }
+        }
+        Ok(())
+    }
+
+    //writes a line of context around the selected lines
+    fn context<W: Write + ?Sized>(&self, out: &mut W, record: &Record) -> MyResult<()> {
+        if self.json {
+            return self.json_line(out, "context", record);
+        }
+        self.prefix(out, '-', Some((record.line_number, record.offset)))?;
+        out.write_all(record.text())?;
+        writeln!(out)?;
+        Ok(())
+    }
+
+    //writes every match in the record on its own line
+    //the byte offset points at the match rather than at the line
+    fn only_matching<W: Write + ?Sized>(&self, out: &mut W, record: &Record) -> MyResult<()> {
+        for (span, matched) in self.matches(record.text(), &record.spans) {
+            self.prefix(
+                out,
+                ':',
+                Some((record.line_number, record.offset + span.start)),
+            )?;
+            self.colored_match(out, &matched)?;
+            writeln!(out)?;
+        }
+        Ok(())
+    }
+
+    //says that a binary file matches, in place of its lines
+    //JSON has no room for the message, the stats at the end tell the same
+    fn binary_matches<W: Write + ?Sized>(&self, out: &mut W) -> MyResult<()> {
+        if !self.json {
+            writeln!(out, "Binary file {} matches", self.path)?;
+        }
+        Ok(())
+    }
+
+    //writes the path alone on its line for -l and -L
+    fn path<W: Write + ?Sized>(&self, out: &mut W) -> MyResult<()> {
+        self.colored(out, |c| &c.filename, self.path.as_bytes())?;
+        out.write_all(if self.null { b"\0" } else { b"\n" })?;
+        Ok(())
+    }
+
+    //writes the number of selected lines for -c
+    fn count<W: Write + ?Sized>(&self, out: &mut W, count: usize) -> MyResult<()> {
+        self.prefix(out, ':', None)?;
+        writeln!(out, "{}", count)?;
+        Ok(())
+    }
+
+    //the JSON events that open and close the search of a file
+    fn begin<W: Write + ?Sized>(&self, out: &mut W) -> MyResult<()> {
+        if self.json {
+            json_event(
+                out,
+                "begin",
+                json!({ "path": json_data(&path_bytes(self.os_path)) }),
+            )?;
+        }
+        Ok(())
+    }
+
+    fn end<W: Write + ?Sized>(
+        &self,
+        out: &mut W,
+        stats: &Stats,
+        elapsed: Duration,
+    ) -> MyResult<()> {
+        if self.json {
+            json_event(
+                out,
+                "end",
+                json!({
+                    "path": json_data(&path_bytes(self.os_path)),
+                    "stats": json_stats(stats, elapsed),
+                }),
+            )?;
+        }
+        Ok(())
+    }
+
+    //a match or context event, with the spans of the matches in the line
+    fn json_line<W: Write + ?Sized>(
+        &self,
+        out: &mut W,
+        kind: &str,
+        record: &Record,
+    ) -> MyResult<()> {
+        let text = record.text();
+        let submatches: Vec<Value> = record
+            .spans
+            .iter()
+            .map(|span| {
+                json!({
+                    "match": json_data(&text[span.clone()]),
+                    "start": span.start,
+                    "end": span.end,
+                })
+            })
+            .collect();
+
+        json_event(
+            out,
+            kind,
+            json!({
+                "path": json_data(&path_bytes(self.os_path)),
+                "lines": json_data(&record.bytes),
+                "line_number": record.line_number,
+                "absolute_offset": record.offset,
+                "submatches": submatches,
+            }),
+        )
+    }
+}
+
+//writes one JSON event on its own line
+fn json_event<W: Write + ?Sized>(out: &mut W, kind: &str, data: Value) -> MyResult<()> {
+    serde_json::to_writer(&mut *out, &json!({ "type": kind, "data": data }))?;
+    writeln!(out)?;
     Ok(())
 }
+
+//the final event with the totals of every file searched
+fn json_summary<W: Write>(out: &mut W, stats: &Stats, elapsed: Duration) -> MyResult<()> {
+    json_event(
+        out,
+        "summary",
+        json!({
+            "elapsed_total": json_duration(elapsed),
+            "stats": json_stats(stats, elapsed),
+        }),
+    )
+}
+
+//text that is valid UTF-8 is kept as is, anything else is base64 encoded
+//so that it survives the trip through JSON
+fn json_data(bytes: &[u8]) -> Value {
+    match std::str::from_utf8(bytes) {
+        Ok(text) => json!({ "text": text }),
+        Err(_) => json!({ "bytes": base64::encode(bytes) }),
+    }
+}
+
+//the bytes of a path, which only unix gives as they are
+#[cfg(unix)]
+fn path_bytes(path: &Path) -> Cow<'_, [u8]> {
+    use std::os::unix::ffi::OsStrExt;
+    Cow::Borrowed(path.as_os_str().as_bytes())
+}
+
+#[cfg(not(unix))]
+fn path_bytes(path: &Path) -> Cow<'_, [u8]> {
+    Cow::Owned(path.to_string_lossy().into_owned().into_bytes())
+}
+
+fn json_duration(elapsed: Duration) -> Value {
+    json!({
+        "secs": elapsed.as_secs(),
+        "nanos": elapsed.subsec_nanos(),
+        "human": format!("{:.6}s", elapsed.as_secs_f64()),
+    })
+}
+
+fn json_stats(stats: &Stats, elapsed: Duration) -> Value {
+    json!({
+        "elapsed": json_duration(elapsed),
+        "searches": stats.searches,
+        "searches_with_match": stats.searches_with_match,
+        "bytes_searched": stats.bytes_searched,
+        "matched_lines": stats.matched_lines,
+        "matches": stats.matches,
+    })
+}
+
+fn open(path: &Path) -> MyResult<Box<dyn BufRead>> {
+    if path == Path::new("-") {
+        Ok(Box::new(BufReader::new(io::stdin())))
+    } else {
+        Ok(Box::new(BufReader::new(File::open(path)?)))
+    }
+}
+
+//wraps the input in a decoder when it starts with the magic bytes of a known
+//compression format, and passes it through untouched otherwise
+//concatenated streams, as left by appending to a log, are read to the end
+fn decompress(mut file: Box<dyn BufRead>) -> MyResult<Box<dyn BufRead>> {
+    let magic = file.fill_buf()?;
+    let decoder: Box<dyn Read> = if magic.starts_with(b"\x1f\x8b") {
+        Box::new(MultiGzDecoder::new(file))
+    } else if magic.starts_with(b"BZh") {
+        Box::new(MultiBzDecoder::new(file))
+    } else if magic.starts_with(b"\xfd7zXZ\x00") {
+        Box::new(XzDecoder::new_multi_decoder(file))
+    } else if magic.starts_with(b"\x28\xb5\x2f\xfd") {
+        Box::new(ZstdDecoder::with_buffer(file)?)
+    } else {
+        return Ok(file);
+    };
+    Ok(Box::new(BufReader::new(decoder)))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{
+        build_pattern, decompress, find_files, grep_lines, json_data, parse_colors, write_stats,
+        BinaryFiles, Boundary, Colors, Config, Engine, ListFiles, Matcher, MatcherBuilder,
+        MyResult, Output, Printer, Record, Searcher, Stats, WalkOptions,
+    };
+    use encoding_rs::Encoding;
+    use regex::bytes::{Regex, RegexBuilder};
+    use serde_json::{json, Value};
+    use std::io::{self, BufReader, Cursor, Read, Write};
+    use std::sync::atomic::{AtomicBool, Ordering};
+    use std::sync::Arc;
+    use std::time::Duration;
+
+    //the groups only matter between inputs
+    impl Output for Vec<u8> {
+        fn begin_group(&mut self) -> MyResult<()> {
+            Ok(())
+        }
+    }
+
+    //collects what find_files finds in one path
+    fn find(path: &str, recursive: bool) -> Vec<MyResult<String>> {
+        let mut files = vec![];
+        find_files(
+            &[path.to_string()],
+            recursive,
+            &WalkOptions::default(),
+            |entry| {
+                files.push(entry.map(|path| path.display().to_string()));
+                true
+            },
+        );
+        files
+    }
+
+    #[test]
+    fn test_find_files() {
+        //verify that the function finds a file known to exist
+        let files = find("./tests/inputs/fox.txt", false);
+        assert_eq!(files.len(), 1);
+        assert_eq!(files[0].as_ref().unwrap(), "./tests/inputs/fox.txt");
+
+        //the function should reject a directory without the recursive option
+        let files = find("./tests/inputs", false);
+        assert_eq!(files.len(), 1);
+        if let Err(e) = &files[0] {
+            assert_eq!(e.to_string(), "./tests/inputs is a directory");
+        }
+
+        //verify the function recurses to find four files in the directory
+        let res = find("./tests/inputs", true);
+        let mut files: Vec<String> = res
+            .iter()
+            .map(|r| r.as_ref().unwrap().replace('\\', "/"))
+            .collect();
+        files.sort();
+        assert_eq!(files.len(), 4);
+        assert_eq!(
+            files,
+            vec![
+                "./tests/inputs/bustle.txt",
+                "./tests/inputs/empty.txt",
+                "./tests/inputs/fox.txt",
+                "./tests/inputs/nobody.txt",
+            ]
+        );
+
+        //a path that does not exist is reported as an error
+        let files = find("./tests/inputs/no-such-file", false);
+        assert_eq!(files.len(), 1);
+        assert!(files[0].is_err());
+    }
+
+    //a config searching for the pattern with every other option turned off
+    fn config(pattern: Regex) -> Config {
+        Config {
+            searcher: Searcher::new(Matcher(Engine::Regex(pattern))),
+            files: vec![],
+            recursive: false,
+            count: false,
+            only_matching: false,
+            colors: None,
+            json: false,
+            walk: WalkOptions::default(),
+            threads: 1,
+            unordered: false,
+            line_number: false,
+            byte_offset: false,
+            with_filename: None,
+            null: false,
+            list_files: None,
+            quiet: false,
+            replace: None,
+            count_matches: false,
+            stats: false,
+        }
+    }
+
+    //runs grep_lines over the text and returns the count and what was written
+    fn grep(text: &str, config: &Config, filename: Option<&str>) -> (usize, String) {
+        let printer = Printer::new(filename.unwrap_or("-"), filename.is_some(), config);
+        let mut out = vec![];
+        let stats = grep_lines(Cursor::new(text), &printer, config, &mut out).unwrap();
+        (stats.matched_lines, String::from_utf8(out).unwrap())
+    }
+
+    #[test]
+    fn test_grep_lines() {
+        let text = "Lorem\nIpsum\r\nDOLOR";
+
+        //the pattern "or" should match the one line, "Lorem"
+        let mut cfg = config(Regex::new("or").unwrap());
+        assert_eq!(grep(text, &cfg, None), (1, "Lorem\n".to_string()));
+
+        //when inverted, the function should match the other two lines
+        cfg.searcher.invert_match = true;
+        assert_eq!(grep(text, &cfg, None), (2, "Ipsum\r\nDOLOR\n".to_string()));
+
+        //this regex will be case-insensitive
+        let re = RegexBuilder::new("or")
+            .case_insensitive(true)
+            .build()
+            .unwrap();
+
+        //the two lines "Lorem" and "DOLOR" should match
+        let cfg = config(re);
+        assert_eq!(grep(text, &cfg, None), (2, "Lorem\nDOLOR\n".to_string()));
+
+        //the line ending is not part of the text being matched
+        let cfg = config(Regex::new("m$").unwrap());
+        assert_eq!(grep(text, &cfg, Some("f")), (1, "f:Lorem\n".to_string()));
+
+        //counting writes nothing
+        let mut cfg = config(Regex::new("m").unwrap());
+        cfg.count = true;
+        assert_eq!(grep(text, &cfg, None), (2, "".to_string()));
+    }
+
+    #[test]
+    fn test_grep_lines_context() {
+        let text = "1\n2\nmatch\n4\n5\n6\n7\nmatch\n9\nmatch\n11\n";
+
+        //windows that don't touch are separated with "--"
+        let mut cfg = config(Regex::new("match").unwrap());
+        cfg.searcher.before_context = 1;
+        cfg.searcher.after_context = 1;
+        assert_eq!(
+            grep(text, &cfg, Some("f")).1,
+            "f-2\nf:match\nf-4\n--\nf-7\nf:match\nf-9\nf:match\nf-11\n"
+        );
+
+        //windows that overlap or touch are merged into one group
+        cfg.searcher.before_context = 2;
+        cfg.searcher.after_context = 2;
+        assert_eq!(
+            grep(text, &cfg, None).1,
+            "1\n2\nmatch\n4\n5\n6\n7\nmatch\n9\nmatch\n11\n"
+        );
+
+        //leading context never reaches past the start of the file
+        cfg.searcher.before_context = 5;
+        cfg.searcher.after_context = 0;
+        assert!(grep(text, &cfg, None).1.starts_with("1\n2\nmatch\n4\n"));
+
+        //leading context alone
+        cfg.searcher.before_context = 1;
+        assert_eq!(
+            grep(text, &cfg, None).1,
+            "2\nmatch\n--\n7\nmatch\n9\nmatch\n"
+        );
+
+        //without context, there are no separators
+        let cfg = config(Regex::new("match").unwrap());
+        assert_eq!(grep(text, &cfg, None).1, "match\nmatch\nmatch\n");
+    }
+
+    #[test]
+    fn test_only_matching() {
+        let text = "foo bar foo\nbaz\nfoofoo\n";
+        let mut cfg = config(Regex::new("fo*").unwrap());
+        cfg.only_matching = true;
+
+        //every match is printed on its own line
+        assert_eq!(
+            grep(text, &cfg, Some("f")),
+            (2, "f:foo\nf:foo\nf:foo\nf:foo\n".to_string())
+        );
+
+        //empty matches are skipped
+        let mut cfg = config(Regex::new("x*").unwrap());
+        cfg.only_matching = true;
+        assert_eq!(grep(text, &cfg, None), (3, "".to_string()));
+
+        //an inverted match has no matched parts to print
+        let mut cfg = config(Regex::new("baz").unwrap());
+        cfg.only_matching = true;
+        cfg.searcher.invert_match = true;
+        assert_eq!(grep(text, &cfg, None), (2, "".to_string()));
+    }
+
+    #[test]
+    fn test_color() {
+        let mut cfg = config(Regex::new("o").unwrap());
+        cfg.colors = Some(Colors::default());
+        cfg.searcher.after_context = 1;
+        assert_eq!(
+            grep("foo\nbar\n", &cfg, Some("f")).1,
+            "\x1b[35m\x1b[Kf\x1b[m\x1b[K\x1b[36m\x1b[K:\x1b[m\x1b[K\
+             f\x1b[01;31m\x1b[Ko\x1b[m\x1b[K\x1b[01;31m\x1b[Ko\x1b[m\x1b[K\n\
+             \x1b[35m\x1b[Kf\x1b[m\x1b[K\x1b[36m\x1b[K-\x1b[m\x1b[Kbar\n"
+        );
+    }
+
+    #[test]
+    fn test_parse_colors() {
+        assert_eq!(parse_colors(""), Colors::default());
+
+        let colors = parse_colors("mt=01;32:fn=34:ln=33:se=:bogus");
+        assert_eq!(
+            colors,
+            Colors {
+                matched: "01;32".to_string(),
+                filename: "34".to_string(),
+                line_number: "33".to_string(),
+                byte_offset: "32".to_string(),
+                separator: "".to_string(),
+            }
+        );
+    }
+
+    #[test]
+    fn test_build_pattern() {
+        let patterns = vec!["fox".to_string(), "^the".to_string()];
+
+        //any of the patterns can match
+        let re = build_pattern(&patterns, false, false, false, None).unwrap();
+        assert!(re.is_match(b"a fox"));
+        assert!(re.is_match(b"the end"));
+        assert!(!re.is_match(b"The end"));
+
+        //case-insensitivity applies to every pattern
+        let re = build_pattern(&patterns, true, false, false, None).unwrap();
+        assert!(re.is_match(b"A FOX"));
+        assert!(re.is_match(b"The end"));
+
+        //the error names the pattern that failed
+        let patterns = vec!["fox".to_string(), "*foo".to_string()];
+        let res = build_pattern(&patterns, false, false, false, None);
+        assert!(res.is_err());
+        assert_eq!(res.unwrap_err().to_string(), "Invalid pattern \"*foo\"");
+
+        //no patterns match nothing
+        let re = build_pattern(&[], false, false, false, None).unwrap();
+        assert!(!re.is_match(b""));
+        assert!(!re.is_match(b"anything"));
+    }
+
+    #[test]
+    fn test_build_pattern_fixed() {
+        let patterns = vec!["a.b[0]".to_string(), "fox".to_string()];
+
+        //metacharacters are taken literally
+        let re = build_pattern(&patterns, false, true, false, None).unwrap();
+        assert!(matches!(re.0, Engine::Literals(_)));
+        assert!(re.is_match(b"x = a.b[0];"));
+        assert!(!re.is_match(b"x = axb0"));
+        assert!(re.is_match(b"a fox"));
+        assert!(!re.is_match(b"a FOX"));
+        assert_eq!(
+            re.find_iter(b"a.b[0] fox").collect::<Vec<_>>(),
+            vec![0..6, 7..10]
+        );
+
+        //case-insensitive literals
+        let re = build_pattern(&patterns, true, true, false, None).unwrap();
+        assert!(re.is_match(b"A.B[0]"));
+        assert!(re.is_match(b"a FOX"));
+
+        //non-ASCII literals still fold their case
+        let re = build_pattern(&["ÉTÉ".to_string()], true, true, false, None).unwrap();
+        assert!(re.is_match("un été".as_bytes()));
+
+        //patterns that are invalid as a regex are fine as literals
+        assert!(build_pattern(&["*foo".to_string()], false, true, false, None).is_ok());
+    }
+
+    #[test]
+    fn test_json() {
+        let mut cfg = config(Regex::new("o").unwrap());
+        cfg.json = true;
+        cfg.searcher.after_context = 1;
+        let (count, out) = grep("foo\nbar\n", &cfg, Some("f"));
+        assert_eq!(count, 1);
+
+        let events: Vec<Value> = out
+            .lines()
+            .map(|line| serde_json::from_str(line).unwrap())
+            .collect();
+        assert_eq!(
+            events,
+            vec![
+                json!({"type": "match", "data": {
+                    "path": {"text": "f"},
+                    "lines": {"text": "foo\n"},
+                    "line_number": 1,
+                    "absolute_offset": 0,
+                    "submatches": [
+                        {"match": {"text": "o"}, "start": 1, "end": 2},
+                        {"match": {"text": "o"}, "start": 2, "end": 3},
+                    ],
+                }}),
+                json!({"type": "context", "data": {
+                    "path": {"text": "f"},
+                    "lines": {"text": "bar\n"},
+                    "line_number": 2,
+                    "absolute_offset": 4,
+                    "submatches": [],
+                }}),
+            ]
+        );
+    }
+
+    #[test]
+    fn test_json_data() {
+        assert_eq!(json_data(b"caf\xc3\xa9"), json!({"text": "café"}));
+        assert_eq!(json_data(b"caf\xe9"), json!({"bytes": "Y2Fm6Q=="}));
+    }
+
+    #[test]
+    #[cfg(unix)]
+    fn test_json_path_not_utf8() {
+        use std::ffi::OsStr;
+        use std::os::unix::ffi::OsStrExt;
+        use std::path::Path;
+
+        let mut cfg = config(Regex::new("o").unwrap());
+        cfg.json = true;
+        let path = Path::new(OsStr::from_bytes(b"caf\xe9"));
+        let mut out = vec![];
+        Printer::new(path, false, &cfg).begin(&mut out).unwrap();
+        let event: Value = serde_json::from_slice(&out).unwrap();
+        assert_eq!(
+            event,
+            json!({"type": "begin", "data": {"path": {"bytes": "Y2Fm6Q=="}}})
+        );
+    }
+
+    #[test]
+    fn test_binary_files() {
+        let text = "abc\0def\nxyz abc\n";
+        let mut cfg = config(Regex::new("abc").unwrap());
+
+        //a binary file only says that it matches
+        assert_eq!(
+            grep(text, &cfg, Some("f")),
+            (1, "Binary file f matches\n".to_string())
+        );
+
+        //but its lines are still counted
+        cfg.count = true;
+        assert_eq!(grep(text, &cfg, None), (2, "".to_string()));
+
+        //unless it is not searched at all
+        cfg.searcher.binary_files = BinaryFiles::WithoutMatch;
+        assert_eq!(grep(text, &cfg, None), (0, "".to_string()));
+
+        //or searched as text
+        cfg.count = false;
+        cfg.searcher.binary_files = BinaryFiles::Text;
+        assert_eq!(
+            grep(text, &cfg, None),
+            (2, "abc\0def\nxyz abc\n".to_string())
+        );
+
+        //a binary file that doesn't match says nothing
+        let cfg = config(Regex::new("nope").unwrap());
+        assert_eq!(grep(text, &cfg, None), (0, "".to_string()));
+    }
+
+    #[test]
+    fn test_invalid_utf8() {
+        //lines that are not valid UTF-8 are searched and written as they are
+        let cfg = config(Regex::new("caf").unwrap());
+        let printer = Printer::new("-", false, &cfg);
+        let mut out = vec![];
+        let stats = grep_lines(Cursor::new(b"caf\xe9\nthe\n"), &printer, &cfg, &mut out).unwrap();
+        assert_eq!(stats.matched_lines, 1);
+        assert_eq!(out, b"caf\xe9\n");
+    }
+
+    #[test]
+    fn test_line_number_byte_offset() {
+        let text = "one\ntwo\nthree\n";
+        let mut cfg = config(Regex::new("t").unwrap());
+        cfg.line_number = true;
+        cfg.byte_offset = true;
+        cfg.searcher.before_context = 1;
+
+        //context lines use '-' between the parts of the prefix
+        assert_eq!(
+            grep(text, &cfg, Some("f")).1,
+            "f-1-0-one\nf:2:4:two\nf:3:8:three\n"
+        );
+
+        //with -o, the byte offset is the one of the match
+        cfg.only_matching = true;
+        assert_eq!(grep(text, &cfg, None).1, "2:4:t\n3:8:t\n");
+
+        //the filename ends with a NUL byte, the count has no line number
+        let mut cfg = config(Regex::new("t").unwrap());
+        cfg.null = true;
+        cfg.line_number = true;
+        assert_eq!(grep(text, &cfg, Some("f")).1, "f\x002:two\nf\x003:three\n");
+        cfg.count = true;
+        let printer = Printer::new("f", true, &cfg);
+        let mut out = vec![];
+        printer.count(&mut out, 2).unwrap();
+        assert_eq!(out, b"f\x002\n");
+    }
+
+    #[test]
+    fn test_list_files() {
+        //a reader that fails past its first line, to show that nothing more is read
+        struct FirstLine(bool);
+        impl Read for FirstLine {
+            fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+                if self.0 {
+                    return Err(io::Error::other("read too far"));
+                }
+                self.0 = true;
+                buf[..4].copy_from_slice(b"foo\n");
+                Ok(4)
+            }
+        }
+
+        let mut cfg = config(Regex::new("foo").unwrap());
+        cfg.list_files = Some(ListFiles::WithMatches);
+        let printer = Printer::new("f", true, &cfg);
+        let mut out = vec![];
+        let stats = grep_lines(BufReader::new(FirstLine(false)), &printer, &cfg, &mut out).unwrap();
+        assert_eq!(stats.matched_lines, 1);
+        assert!(out.is_empty());
+
+        //the path ends with a NUL byte instead of a newline
+        cfg.null = true;
+        let mut out = vec![];
+        Printer::new("f", false, &cfg).path(&mut out).unwrap();
+        assert_eq!(out, b"f\0");
+    }
+
+    #[test]
+    fn test_max_count() {
+        let text = "a1\nb\na2\na3\nb\n";
+        let mut cfg = config(Regex::new("a").unwrap());
+        cfg.searcher.max_count = Some(2);
+        assert_eq!(grep(text, &cfg, None), (2, "a1\na2\n".to_string()));
+
+        //the trailing context is still written, even when it would match
+        cfg.searcher.after_context = 1;
+        assert_eq!(grep(text, &cfg, None), (2, "a1\nb\na2\na3\n".to_string()));
+
+        //zero stops before the first line
+        cfg.searcher.max_count = Some(0);
+        assert_eq!(grep(text, &cfg, None), (0, "".to_string()));
+    }
+
+    #[test]
+    fn test_multiline() {
+        let text = "fn foo(\n    x: u8,\n) {}\nfn bar() {}\n";
+        let mut cfg = config(Regex::new(r"\(\n\s+x").unwrap());
+        cfg.searcher.multiline = true;
+        cfg.line_number = true;
+
+        //the whole block is written after the number of its first line
+        assert_eq!(
+            grep(text, &cfg, None),
+            (2, "1:fn foo(\n    x: u8,\n".to_string())
+        );
+
+        //matches that share a line are one block, the context goes around it
+        let mut cfg = config(Regex::new(r"u8,\n\)| \{\}\nfn").unwrap());
+        cfg.searcher.multiline = true;
+        cfg.searcher.before_context = 1;
+        cfg.searcher.after_context = 1;
+        assert_eq!(
+            grep(text, &cfg, None),
+            (3, "fn foo(\n    x: u8,\n) {}\nfn bar() {}\n".to_string())
+        );
+
+        //inverting selects the lines that no match touched
+        cfg.searcher.before_context = 0;
+        cfg.searcher.after_context = 0;
+        cfg.searcher.invert_match = true;
+        assert_eq!(grep(text, &cfg, None), (1, "fn foo(\n".to_string()));
+
+        //'.' only crosses lines with dotall
+        let patterns = ["foo.*bar".to_string()];
+        let mut cfg = config(Regex::new("x").unwrap());
+        cfg.searcher.multiline = true;
+        cfg.searcher.matcher = build_pattern(&patterns, false, false, false, None).unwrap();
+        assert_eq!(grep(text, &cfg, None).0, 0);
+        cfg.searcher.matcher = build_pattern(&patterns, false, false, true, None).unwrap();
+        assert_eq!(grep(text, &cfg, None).0, 4);
+    }
+
+    #[test]
+    fn test_replace() {
+        let text = "foo_bar baz_qux\n";
+        let mut cfg = config(Regex::new(r"(?P<a>\w+)_(\w+)").unwrap());
+        //a name runs on as far as it can, so ${2} keeps the _ out of it
+        cfg.replace = Some("${2}_$a".to_string());
+        assert_eq!(grep(text, &cfg, None).1, "bar_foo qux_baz\n");
+
+        //with -o, each replacement is written on its own
+        cfg.only_matching = true;
+        assert_eq!(grep(text, &cfg, None).1, "bar_foo\nqux_baz\n");
+
+        //fixed strings have no groups to expand
+        let mut cfg = config(Regex::new("x").unwrap());
+        cfg.searcher.matcher =
+            build_pattern(&["ba".to_string()], false, true, false, None).unwrap();
+        cfg.replace = Some("[$0]".to_string());
+        assert_eq!(grep(text, &cfg, None).1, "foo_[$0]r [$0]z_qux\n");
+    }
+
+    #[test]
+    fn test_decompress() {
+        let text = b"foo\nbar\n";
+        let mut gz = flate2::write::GzEncoder::new(vec![], flate2::Compression::default());
+        gz.write_all(text).unwrap();
+        let mut bz = bzip2::write::BzEncoder::new(vec![], bzip2::Compression::default());
+        bz.write_all(text).unwrap();
+        let mut xz = xz2::write::XzEncoder::new(vec![], 6);
+        xz.write_all(text).unwrap();
+        let compressed = [
+            gz.finish().unwrap(),
+            bz.finish().unwrap(),
+            xz.finish().unwrap(),
+            zstd::encode_all(&text[..], 0).unwrap(),
+            //anything else is read as it is
+            text.to_vec(),
+        ];
+
+        for input in compressed {
+            let mut file = decompress(Box::new(Cursor::new(input))).unwrap();
+            let mut out = vec![];
+            file.read_to_end(&mut out).unwrap();
+            assert_eq!(out, text);
+        }
+    }
+
+    #[test]
+    fn test_word_and_line_boundary() {
+        let words = |pattern: &str, text: &str| -> Vec<String> {
+            let re = build_pattern(
+                &[pattern.to_string()],
+                false,
+                false,
+                false,
+                Some(Boundary::Word),
+            )
+            .unwrap();
+            re.find_iter(text.as_bytes())
+                .map(|span| text[span].to_string())
+                .collect()
+        };
+        assert_eq!(words("foo", "foobar foo_ foo."), vec!["foo"]);
+        //a match that runs into a word is tried shorter before moving on
+        assert_eq!(words("foo.*", "foo bar baz"), vec!["foo bar baz"]);
+        assert_eq!(words("a.*b", "ab xbc"), vec!["ab"]);
+        assert_eq!(words("x*", "xx yxy"), vec!["xx"]);
+        //anything that isn't a letter, a digit or '_' is a boundary, not just ASCII
+        assert_eq!(words("été", "—été—étés"), vec!["été"]);
+        //a match that starts inside a word is skipped along with the rest of the word
+        assert_eq!(words("x+", "axxx xx"), vec!["xx"]);
+        let long = format!("a{}", "x".repeat(100_000));
+        assert!(words("x+", &long).is_empty());
+
+        let re = build_pattern(
+            &["a|ab".to_string()],
+            false,
+            false,
+            false,
+            Some(Boundary::Line),
+        )
+        .unwrap();
+        assert!(re.is_match(b"ab"));
+        assert!(!re.is_match(b"abc"));
+        let re = build_pattern(
+            &["a.b".to_string()],
+            true,
+            true,
+            false,
+            Some(Boundary::Line),
+        )
+        .unwrap();
+        assert!(re.is_match(b"A.B"));
+        assert!(!re.is_match(b"axb"));
+    }
+
+    #[test]
+    fn test_stats() {
+        let mut cfg = config(Regex::new("o").unwrap());
+        cfg.count_matches = true;
+        let printer = Printer::new("f", false, &cfg);
+        let mut out = vec![];
+        let text = "foo\nbar\nboo\n";
+        let stats = grep_lines(Cursor::new(text), &printer, &cfg, &mut out).unwrap();
+        assert!(out.is_empty());
+        assert_eq!(
+            stats,
+            Stats {
+                searches: 1,
+                searches_with_match: 1,
+                bytes_searched: 12,
+                lines_searched: 3,
+                matched_lines: 2,
+                matches: 4,
+                errors: 0,
+            }
+        );
+
+        let mut out = vec![];
+        write_stats(&mut out, &stats, Duration::from_millis(1500)).unwrap();
+        assert_eq!(
+            String::from_utf8(out).unwrap(),
+            "1 files searched\n1 files matched\n3 lines searched\n12 bytes searched\n\
+             2 matched lines\n4 matches\n1.500000 seconds\n"
+        );
+    }
+
+    #[test]
+    fn test_searcher() {
+        let matcher = MatcherBuilder::new()
+            .case_insensitive(true)
+            .build(&["fo+"])
+            .unwrap();
+        let mut searcher = Searcher::new(matcher);
+        searcher.after_context(1);
+        let text = "Foo foo\nbar\nbaz\nfoo";
+        let records: Vec<Record> = searcher
+            .search_reader("f", text.as_bytes())
+            .unwrap()
+            .map(Result::unwrap)
+            .collect();
+        let record = |line_number, offset, bytes: &str, spans: &[(usize, usize)], context| Record {
+            path: "f".to_string(),
+            line_number,
+            offset,
+            bytes: bytes.as_bytes().to_vec(),
+            spans: spans.iter().map(|&(start, end)| start..end).collect(),
+            context,
+        };
+        assert_eq!(
+            records,
+            vec![
+                record(1, 0, "Foo foo\n", &[(0, 3), (4, 7)], false),
+                record(2, 8, "bar\n", &[], true),
+                record(4, 16, "foo", &[(0, 3)], false),
+            ]
+        );
+
+        //a multiline record holds every line its match touches
+        searcher.after_context(0).multiline(true);
+        searcher.matcher = Matcher::new(r"foo\nbar").unwrap();
+        let mut search = searcher.search_reader("f", text.as_bytes()).unwrap();
+        let first = search.next().unwrap().unwrap();
+        assert_eq!(first, record(1, 0, "Foo foo\nbar\n", &[(4, 11)], false));
+        assert_eq!(first.last_line_number(), 2);
+        assert!(search.next().is_none());
+        assert_eq!(search.stats().matched_lines, 2);
+
+        //the sink stops the search by returning false
+        searcher.multiline(false);
+        searcher.matcher = Matcher::new("a").unwrap();
+        let mut seen = vec![];
+        let stats = searcher
+            .search_sink(
+                "f",
+                text.as_bytes(),
+                &mut |record: &Record| -> MyResult<bool> {
+                    seen.push(record.line_number);
+                    Ok(false)
+                },
+            )
+            .unwrap();
+        assert_eq!(seen, vec![2]);
+        assert_eq!(stats.lines_searched, 2);
+
+        //raising the stop flag ends the search before the next line is read
+        let stop = Arc::new(AtomicBool::new(false));
+        searcher.stop_flag(Arc::clone(&stop));
+        let mut search = searcher.search_reader("f", text.as_bytes()).unwrap();
+        assert_eq!(search.next().unwrap().unwrap().line_number, 2);
+        stop.store(true, Ordering::Relaxed);
+        assert!(search.next().is_none());
+        assert_eq!(search.stats().lines_searched, 2);
+    }
+
+    #[test]
+    fn test_encoding() {
+        let searcher = Searcher::new(Matcher::new("é").unwrap());
+        let first = |searcher: &Searcher, input: &[u8]| {
+            let mut search = searcher.search_reader("f", input).unwrap();
+            let record = search.next().map(Result::unwrap);
+            (search.binary(), record)
+        };
+
+        //UTF-16 is found by its BOM, and the record points into the decoded text
+        let mut utf16 = vec![0xff, 0xfe];
+        utf16.extend("a\nbé\n".encode_utf16().flat_map(u16::to_le_bytes));
+        let (binary, record) = first(&searcher, &utf16);
+        assert!(!binary);
+        let record = record.unwrap();
+        assert_eq!((record.line_number, record.offset), (2, 2));
+        assert_eq!(record.bytes, "bé\n".as_bytes());
+        assert_eq!(record.spans.len(), 1);
+        assert_eq!(&record.bytes[record.spans[0].clone()], "é".as_bytes());
+
+        //other encodings have to be named
+        let latin1 = b"caf\xe9\n";
+        assert!(first(&searcher, latin1).1.is_none());
+        let mut searcher = searcher;
+        searcher.encoding(Encoding::for_label(b"latin1"));
+        assert_eq!(
+            first(&searcher, latin1).1.unwrap().bytes,
+            "café\n".as_bytes()
+        );
+    }
+}
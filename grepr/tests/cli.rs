@@ -0,0 +1,1125 @@
+use assert_cmd::Command;
+use predicates::prelude::*;
+use rand::{distributions::Alphanumeric, Rng};
+use std::{
+    env, fs,
+    path::{Path, PathBuf},
+};
+use sys_info::os_type;
+
+type TestResult = Result<(), Box<dyn std::error::Error>>;
+
+const PRG: &str = "grepr";
+const BUSTLE: &str = "tests/inputs/bustle.txt";
+const EMPTY: &str = "tests/inputs/empty.txt";
+const FOX: &str = "tests/inputs/fox.txt";
+const NOBODY: &str = "tests/inputs/nobody.txt";
+const INPUTS_DIR: &str = "tests/inputs";
+const PATTERNS: &str = "tests/patterns/fox_im.txt";
+
+// --------------------------------------------------
+fn gen_bad_file() -> String {
+    loop {
+        let filename: String = rand::thread_rng()
+            .sample_iter(&Alphanumeric)
+            .take(7)
+            .map(char::from)
+            .collect();
+
+        if fs::metadata(&filename).is_err() {
+            return filename;
+        }
+    }
+}
+
+// --------------------------------------------------
+#[test]
+fn dies_no_args() -> TestResult {
+    Command::cargo_bin(PRG)?
+        .assert()
+        .failure()
+        .stderr(predicate::str::contains("USAGE"));
+    Ok(())
+}
+
+// --------------------------------------------------
+#[test]
+fn dies_bad_pattern() -> TestResult {
+    Command::cargo_bin(PRG)?
+        .args(["*foo", FOX])
+        .assert()
+        .failure()
+        .stderr(predicate::str::contains("Invalid pattern \"*foo\""));
+    Ok(())
+}
+
+// --------------------------------------------------
+#[test]
+fn warns_bad_file() -> TestResult {
+    let bad = gen_bad_file();
+    let expected = format!("{}: .* [(]os error 2[)]", bad);
+    Command::cargo_bin(PRG)?
+        .args(["foo", &bad])
+        .assert()
+        .stderr(predicate::str::is_match(expected)?);
+    Ok(())
+}
+
+// --------------------------------------------------
+fn run(args: &[&str], expected_file: &str) -> TestResult {
+    let windows_file = format!("{}.windows", expected_file);
+    let expected_file = if os_type().unwrap() == "Windows" && Path::new(&windows_file).is_file() {
+        &windows_file
+    } else {
+        expected_file
+    };
+
+    let expected = fs::read_to_string(expected_file)?;
+
+    Command::cargo_bin(PRG)?
+        .args(args)
+        .assert()
+        .stdout(expected);
+    Ok(())
+}
+
+// --------------------------------------------------
+#[test]
+fn empty_file() -> TestResult {
+    run(&["foo", EMPTY], "tests/expected/empty.foo")
+}
+
+// --------------------------------------------------
+#[test]
+fn empty_regex() -> TestResult {
+    run(&["", FOX], "tests/expected/empty_regex.fox.txt")
+}
+
+// --------------------------------------------------
+#[test]
+fn bustle_capitalized() -> TestResult {
+    run(
+        &["The", BUSTLE],
+        "tests/expected/bustle.txt.the.capitalized",
+    )
+}
+
+// --------------------------------------------------
+#[test]
+fn bustle_lowercase() -> TestResult {
+    run(&["the", BUSTLE], "tests/expected/bustle.txt.the.lowercase")
+}
+
+// --------------------------------------------------
+#[test]
+fn bustle_insensitive() -> TestResult {
+    run(
+        &["--insensitive", "the", BUSTLE],
+        "tests/expected/bustle.txt.the.lowercase.insensitive",
+    )
+}
+
+// --------------------------------------------------
+#[test]
+fn nobody() -> TestResult {
+    run(&["nobody", NOBODY], "tests/expected/nobody.txt")
+}
+
+// --------------------------------------------------
+#[test]
+fn nobody_insensitive() -> TestResult {
+    run(
+        &["-i", "nobody", NOBODY],
+        "tests/expected/nobody.txt.insensitive",
+    )
+}
+
+// --------------------------------------------------
+#[test]
+fn multiple_files() -> TestResult {
+    run(
+        &["The", BUSTLE, EMPTY, FOX, NOBODY],
+        "tests/expected/all.the.capitalized",
+    )
+}
+
+// --------------------------------------------------
+#[test]
+fn multiple_files_insensitive() -> TestResult {
+    run(
+        &["-i", "the", BUSTLE, EMPTY, FOX, NOBODY],
+        "tests/expected/all.the.lowercase.insensitive",
+    )
+}
+
+// --------------------------------------------------
+#[test]
+fn recursive() -> TestResult {
+    run(
+        &["--recursive", "dog", INPUTS_DIR],
+        "tests/expected/dog.recursive",
+    )
+}
+
+// --------------------------------------------------
+#[test]
+fn recursive_insensitive() -> TestResult {
+    run(
+        &["-ri", "then", INPUTS_DIR],
+        "tests/expected/the.recursive.insensitive",
+    )
+}
+
+// --------------------------------------------------
+#[test]
+fn sensitive_count_capital() -> TestResult {
+    run(
+        &["--count", "The", BUSTLE],
+        "tests/expected/bustle.txt.the.capitalized.count",
+    )
+}
+
+// --------------------------------------------------
+#[test]
+fn sensitive_count_lower() -> TestResult {
+    run(
+        &["--count", "the", BUSTLE],
+        "tests/expected/bustle.txt.the.lowercase.count",
+    )
+}
+
+// --------------------------------------------------
+#[test]
+fn insensitive_count() -> TestResult {
+    run(
+        &["-ci", "the", BUSTLE],
+        "tests/expected/bustle.txt.the.lowercase.insensitive.count",
+    )
+}
+
+// --------------------------------------------------
+#[test]
+fn nobody_count() -> TestResult {
+    run(&["-c", "nobody", NOBODY], "tests/expected/nobody.txt.count")
+}
+
+// --------------------------------------------------
+#[test]
+fn nobody_count_insensitive() -> TestResult {
+    run(
+        &["-ci", "nobody", NOBODY],
+        "tests/expected/nobody.txt.insensitive.count",
+    )
+}
+
+// --------------------------------------------------
+#[test]
+fn sensitive_count_multiple() -> TestResult {
+    run(
+        &["-c", "The", BUSTLE, EMPTY, FOX, NOBODY],
+        "tests/expected/all.the.capitalized.count",
+    )
+}
+
+// --------------------------------------------------
+#[test]
+fn insensitive_count_multiple() -> TestResult {
+    run(
+        &["-ic", "the", BUSTLE, EMPTY, FOX, NOBODY],
+        "tests/expected/all.the.lowercase.insensitive.count",
+    )
+}
+
+// --------------------------------------------------
+#[test]
+fn warns_dir_not_recursive() -> TestResult {
+    let stdout = "tests/inputs/fox.txt:\
+        The quick brown fox jumps over the lazy dog.";
+    Command::cargo_bin(PRG)?
+        .args(["fox", INPUTS_DIR, FOX])
+        .assert()
+        .stderr(predicate::str::contains("tests/inputs is a directory"))
+        .stdout(predicate::str::contains(stdout));
+    Ok(())
+}
+
+// --------------------------------------------------
+#[test]
+fn stdin() -> TestResult {
+    let input = fs::read_to_string(BUSTLE)?;
+    let expected = fs::read_to_string("tests/expected/bustle.txt.the.capitalized")?;
+
+    Command::cargo_bin(PRG)?
+        .arg("The")
+        .write_stdin(input)
+        .assert()
+        .stdout(expected);
+    Ok(())
+}
+
+// --------------------------------------------------
+#[test]
+fn stdin_insensitive_count() -> TestResult {
+    let files = &[BUSTLE, EMPTY, FOX, NOBODY];
+
+    let mut input = String::new();
+    for file in files {
+        input += &fs::read_to_string(file)?;
+    }
+
+    let expected_file = "tests/expected/the.recursive.insensitive.count.stdin";
+    let expected = fs::read_to_string(expected_file)?;
+
+    Command::cargo_bin(PRG)?
+        .args(["-ci", "the", "-"])
+        .write_stdin(input)
+        .assert()
+        .stdout(expected);
+    Ok(())
+}
+
+// --------------------------------------------------
+#[test]
+fn bustle_after_context() -> TestResult {
+    run(
+        &["-A", "1", "The", BUSTLE],
+        "tests/expected/bustle.txt.the.after1",
+    )
+}
+
+// --------------------------------------------------
+#[test]
+fn nobody_before_context() -> TestResult {
+    run(
+        &["--before-context", "2", "you", NOBODY],
+        "tests/expected/nobody.txt.you.before2",
+    )
+}
+
+// --------------------------------------------------
+#[test]
+fn multiple_files_context() -> TestResult {
+    run(
+        &["-i", "-C", "1", "the", BUSTLE, EMPTY, FOX, NOBODY],
+        "tests/expected/all.the.lowercase.insensitive.context1",
+    )
+}
+
+// --------------------------------------------------
+#[test]
+fn dies_bad_context() -> TestResult {
+    Command::cargo_bin(PRG)?
+        .args(["-A", "foo", "the", FOX])
+        .assert()
+        .failure()
+        .stderr(predicate::str::contains("Invalid context length \"foo\""));
+    Ok(())
+}
+
+// --------------------------------------------------
+#[test]
+fn only_matching_insensitive() -> TestResult {
+    run(
+        &["-oi", "the", BUSTLE, NOBODY],
+        "tests/expected/bustle.nobody.the.only_matching.insensitive",
+    )
+}
+
+// --------------------------------------------------
+#[test]
+fn color_always_context() -> TestResult {
+    run(
+        &["--color=always", "-C", "1", "The", BUSTLE, FOX],
+        "tests/expected/bustle.fox.the.context1.color",
+    )
+}
+
+// --------------------------------------------------
+#[test]
+fn color_auto_not_a_tty() -> TestResult {
+    run(
+        &["--color", "The", BUSTLE],
+        "tests/expected/bustle.txt.the.capitalized",
+    )
+}
+
+// --------------------------------------------------
+#[test]
+fn color_from_env() -> TestResult {
+    Command::cargo_bin(PRG)?
+        .env("GREPR_COLORS", "mt=01;32")
+        .args(["--color=always", "the", FOX])
+        .assert()
+        .stdout("The quick brown fox jumps over \x1b[01;32m\x1b[Kthe\x1b[m\x1b[K lazy dog.\n");
+    Ok(())
+}
+
+// --------------------------------------------------
+#[test]
+fn multiple_regexps() -> TestResult {
+    run(
+        &["-e", "fox", "-e", "Nobody", FOX, NOBODY],
+        "tests/expected/fox.nobody.regexp",
+    )
+}
+
+// --------------------------------------------------
+#[test]
+fn pattern_file_insensitive() -> TestResult {
+    run(
+        &["-i", "-e", "NOBODY", "-f", PATTERNS, FOX, NOBODY],
+        "tests/expected/fox.nobody.pattern_file.insensitive",
+    )
+}
+
+// --------------------------------------------------
+#[test]
+fn regexp_reads_stdin() -> TestResult {
+    let input = fs::read_to_string(BUSTLE)?;
+    let expected = fs::read_to_string("tests/expected/bustle.txt.the.capitalized")?;
+
+    Command::cargo_bin(PRG)?
+        .args(["-e", "The"])
+        .write_stdin(input)
+        .assert()
+        .stdout(expected);
+    Ok(())
+}
+
+// --------------------------------------------------
+#[test]
+fn dies_bad_regexp() -> TestResult {
+    Command::cargo_bin(PRG)?
+        .args(["-e", "fox", "-e", "*foo", FOX])
+        .assert()
+        .failure()
+        .stderr(predicate::str::contains("Invalid pattern \"*foo\""));
+    Ok(())
+}
+
+// --------------------------------------------------
+#[test]
+fn dies_bad_pattern_file() -> TestResult {
+    let bad = gen_bad_file();
+    let expected = format!("{}: .* [(]os error 2[)]", bad);
+    Command::cargo_bin(PRG)?
+        .args(["-f", &bad, FOX])
+        .assert()
+        .failure()
+        .stderr(predicate::str::is_match(expected)?);
+    Ok(())
+}
+
+// --------------------------------------------------
+#[test]
+fn fixed_strings() -> TestResult {
+    run(
+        &["-F", "you?", NOBODY],
+        "tests/expected/nobody.txt.you.fixed",
+    )
+}
+
+// --------------------------------------------------
+#[test]
+fn fixed_strings_insensitive() -> TestResult {
+    run(
+        &["-Fi", "-e", "NOBODY!", "-e", "FROG", NOBODY],
+        "tests/expected/nobody.txt.fixed.insensitive",
+    )
+}
+
+// --------------------------------------------------
+#[test]
+fn fixed_strings_not_a_regex() -> TestResult {
+    run(
+        &["--fixed-strings", "*foo", FOX],
+        "tests/expected/empty.foo",
+    )
+}
+
+// --------------------------------------------------
+#[test]
+fn json_events() -> TestResult {
+    let output = Command::cargo_bin(PRG)?
+        .args(["--json", "-i", "nobody", NOBODY, FOX])
+        .output()?;
+    assert!(output.status.success());
+
+    let events = String::from_utf8(output.stdout)?
+        .lines()
+        .map(serde_json::from_str)
+        .collect::<Result<Vec<serde_json::Value>, _>>()?;
+    let types: Vec<&str> = events.iter().filter_map(|e| e["type"].as_str()).collect();
+    assert_eq!(
+        types,
+        vec!["begin", "match", "match", "end", "begin", "end", "summary"]
+    );
+
+    let data = &events[2]["data"];
+    assert_eq!(data["path"]["text"], NOBODY);
+    assert_eq!(data["lines"]["text"], "Are you—Nobody—too?\n");
+    assert_eq!(data["line_number"], 2);
+    assert_eq!(data["absolute_offset"], 25);
+    assert_eq!(data["submatches"][0]["match"]["text"], "Nobody");
+    assert_eq!(data["submatches"][0]["start"], 10);
+
+    let stats = &events[6]["data"]["stats"];
+    assert_eq!(stats["searches"], 2);
+    assert_eq!(stats["searches_with_match"], 1);
+    assert_eq!(stats["matched_lines"], 2);
+    Ok(())
+}
+
+// --------------------------------------------------
+#[test]
+fn dies_json_count() -> TestResult {
+    Command::cargo_bin(PRG)?
+        .args(["--json", "-c", "fox", FOX])
+        .assert()
+        .failure()
+        .stderr(predicate::str::contains("cannot be used with"));
+    Ok(())
+}
+
+// --------------------------------------------------
+//builds a directory tree with ignore files, hidden entries and build artifacts,
+//all containing the word "needle"
+fn gen_ignore_tree() -> Result<PathBuf, Box<dyn std::error::Error>> {
+    let dir = env::temp_dir().join(format!("grepr-{}", gen_bad_file()));
+    for subdir in ["skipped", ".hidden", "target", "src"] {
+        fs::create_dir_all(dir.join(subdir))?;
+    }
+    fs::write(dir.join(".gitignore"), "*.log\ntarget/\n")?;
+    fs::write(dir.join(".ignore"), "skipped/\n")?;
+    for file in [
+        "a.txt",
+        "b.log",
+        "Cargo.lock",
+        "skipped/c.txt",
+        ".hidden/d.txt",
+        "target/e.txt",
+        "src/f.rs",
+    ] {
+        fs::write(dir.join(file), "needle\n")?;
+    }
+    Ok(dir)
+}
+
+// --------------------------------------------------
+//searches the tree for "needle" and returns the sorted list of matching files
+fn search_tree(args: &[&str]) -> Result<Vec<String>, Box<dyn std::error::Error>> {
+    let dir = gen_ignore_tree()?;
+    let output = Command::cargo_bin(PRG)?
+        .current_dir(&dir)
+        .args(["-rc", "needle", "."])
+        .args(args)
+        .output();
+    fs::remove_dir_all(&dir)?;
+
+    let stdout = String::from_utf8(output?.stdout)?;
+    let mut files: Vec<String> = stdout
+        .lines()
+        .filter(|line| line.ends_with(":1"))
+        .map(|line| line.trim_end_matches(":1").replace('\\', "/"))
+        .collect();
+    files.sort();
+    Ok(files)
+}
+
+// --------------------------------------------------
+#[test]
+fn recursive_respects_ignore_files() -> TestResult {
+    assert_eq!(
+        search_tree(&[])?,
+        vec!["./Cargo.lock", "./a.txt", "./src/f.rs"]
+    );
+    Ok(())
+}
+
+// --------------------------------------------------
+#[test]
+fn recursive_hidden() -> TestResult {
+    assert_eq!(
+        search_tree(&["--hidden"])?,
+        vec!["./.hidden/d.txt", "./Cargo.lock", "./a.txt", "./src/f.rs"]
+    );
+    Ok(())
+}
+
+// --------------------------------------------------
+#[test]
+fn recursive_no_ignore() -> TestResult {
+    assert_eq!(
+        search_tree(&["--no-ignore"])?,
+        vec![
+            "./Cargo.lock",
+            "./a.txt",
+            "./b.log",
+            "./skipped/c.txt",
+            "./src/f.rs",
+            "./target/e.txt"
+        ]
+    );
+    Ok(())
+}
+
+// --------------------------------------------------
+#[test]
+fn recursive_globs() -> TestResult {
+    assert_eq!(
+        search_tree(&["--glob", "!*.lock"])?,
+        vec!["./a.txt", "./src/f.rs"]
+    );
+    assert_eq!(search_tree(&["-g", "*.rs"])?, vec!["./src/f.rs"]);
+    assert_eq!(
+        search_tree(&["--no-ignore", "-g", "*.log", "-g", "*.lock"])?,
+        vec!["./Cargo.lock", "./b.log"]
+    );
+    Ok(())
+}
+
+// --------------------------------------------------
+#[test]
+fn dies_bad_glob() -> TestResult {
+    Command::cargo_bin(PRG)?
+        .args(["-r", "--glob", "a[", "fox", INPUTS_DIR])
+        .assert()
+        .failure()
+        .stderr(predicate::str::contains("Invalid --glob \"a[\""));
+    Ok(())
+}
+
+// --------------------------------------------------
+//-r with no files searches the current directory instead of waiting on stdin
+#[test]
+fn recursive_defaults_to_current_dir() -> TestResult {
+    for args in [vec!["-rl", "fox"], vec!["-rl", "-e", "fox"]] {
+        let output = Command::cargo_bin(PRG)?
+            .current_dir(INPUTS_DIR)
+            .args(args)
+            .write_stdin("fox\n")
+            .output()?;
+        assert!(output.status.success());
+        assert_eq!(
+            String::from_utf8(output.stdout)?.replace('\\', "/"),
+            "./fox.txt\n"
+        );
+    }
+    Ok(())
+}
+
+// --------------------------------------------------
+//a reader that stops early, like head, ends the search quietly, whether the
+//output comes from workers or straight from a single input
+#[test]
+fn closed_stdout() -> TestResult {
+    use std::io::{BufRead, BufReader};
+    use std::process::Stdio;
+
+    let dir = env::temp_dir().join(format!("grepr-{}", gen_bad_file()));
+    fs::create_dir(&dir)?;
+    for i in 0..20 {
+        fs::write(dir.join(format!("{}.txt", i)), "needle\n".repeat(10_000))?;
+    }
+    let dirname = dir.display().to_string();
+    let first = dir.join("0.txt").display().to_string();
+    let mut results = vec![];
+    for args in [
+        vec!["-r", "-j", "1", "needle", &dirname],
+        vec!["-r", "-j", "4", "needle", &dirname],
+        vec!["needle", &first],
+    ] {
+        let child = std::process::Command::new(assert_cmd::cargo::cargo_bin(PRG))
+            .args(args)
+            .stdout(Stdio::piped())
+            .stderr(Stdio::piped())
+            .spawn();
+        results.push(child.and_then(|mut child| {
+            let mut line = String::new();
+            BufReader::new(child.stdout.take().unwrap()).read_line(&mut line)?;
+            Ok((line, child.wait_with_output()?))
+        }));
+    }
+    fs::remove_dir_all(&dir)?;
+
+    for result in results {
+        let (line, output) = result?;
+        assert!(line.ends_with("needle\n"));
+        assert!(output.status.success());
+        assert_eq!(String::from_utf8(output.stderr)?, "");
+    }
+    Ok(())
+}
+
+// --------------------------------------------------
+//file names don't have to be UTF-8 on Linux, and the walk must still open them
+#[test]
+#[cfg(target_os = "linux")]
+fn recursive_non_utf8_name() -> TestResult {
+    use std::{ffi::OsStr, os::unix::ffi::OsStrExt};
+
+    let dir = env::temp_dir().join(format!("grepr-{}", gen_bad_file()));
+    fs::create_dir(&dir)?;
+    fs::write(dir.join(OsStr::from_bytes(b"caf\xe9.txt")), "needle\n")?;
+    let output = Command::cargo_bin(PRG)?
+        .args(["-r", "needle"])
+        .arg(&dir)
+        .output();
+    fs::remove_dir_all(&dir)?;
+
+    let output = output?;
+    assert!(output.status.success());
+    assert_eq!(
+        String::from_utf8(output.stdout)?,
+        format!("{}/caf\u{FFFD}.txt:needle\n", dir.display())
+    );
+    Ok(())
+}
+
+// --------------------------------------------------
+#[test]
+fn threads_keep_order() -> TestResult {
+    run(
+        &["-j", "4", "-c", "The", BUSTLE, EMPTY, FOX, NOBODY],
+        "tests/expected/all.the.capitalized.count",
+    )?;
+    run(
+        &[
+            "--threads",
+            "3",
+            "-i",
+            "-C",
+            "1",
+            "the",
+            BUSTLE,
+            EMPTY,
+            FOX,
+            NOBODY,
+        ],
+        "tests/expected/all.the.lowercase.insensitive.context1",
+    )
+}
+
+// --------------------------------------------------
+#[test]
+fn unordered() -> TestResult {
+    let output = Command::cargo_bin(PRG)?
+        .args([
+            "--unordered",
+            "-j",
+            "4",
+            "-i",
+            "the",
+            BUSTLE,
+            EMPTY,
+            FOX,
+            NOBODY,
+        ])
+        .output()?;
+    let mut lines: Vec<String> = String::from_utf8(output.stdout)?
+        .lines()
+        .map(String::from)
+        .collect();
+    lines.sort();
+
+    let mut expected: Vec<String> =
+        fs::read_to_string("tests/expected/all.the.lowercase.insensitive")?
+            .lines()
+            .map(String::from)
+            .collect();
+    expected.sort();
+    assert_eq!(lines, expected);
+    Ok(())
+}
+
+// --------------------------------------------------
+#[test]
+fn dies_bad_threads() -> TestResult {
+    Command::cargo_bin(PRG)?
+        .args(["-j", "0", "fox", FOX])
+        .assert()
+        .failure()
+        .stderr(predicate::str::contains("Invalid thread count \"0\""));
+    Ok(())
+}
+
+// --------------------------------------------------
+#[test]
+fn binary_files() -> TestResult {
+    let input = b"abc\0def\nxyz abc\n".to_vec();
+
+    Command::cargo_bin(PRG)?
+        .arg("abc")
+        .write_stdin(input.clone())
+        .assert()
+        .success()
+        .stdout("Binary file - matches\n");
+
+    Command::cargo_bin(PRG)?
+        .args(["-a", "abc"])
+        .write_stdin(input.clone())
+        .assert()
+        .success()
+        .stdout("abc\0def\nxyz abc\n");
+
+    Command::cargo_bin(PRG)?
+        .args(["--binary-files=without-match", "abc"])
+        .write_stdin(input)
+        .assert()
+        .stdout("");
+    Ok(())
+}
+
+// --------------------------------------------------
+#[test]
+fn line_number_byte_offset() -> TestResult {
+    run(
+        &["-n", "-b", "-C", "1", "-i", "the", BUSTLE, NOBODY],
+        "tests/expected/bustle.nobody.the.line_number.byte_offset",
+    )
+}
+
+// --------------------------------------------------
+#[test]
+fn with_and_without_filename() -> TestResult {
+    Command::cargo_bin(PRG)?
+        .args(["-H", "fox", FOX])
+        .assert()
+        .success()
+        .stdout("tests/inputs/fox.txt:The quick brown fox jumps over the lazy dog.\n");
+
+    Command::cargo_bin(PRG)?
+        .args(["-h", "-c", "The", BUSTLE, FOX])
+        .assert()
+        .success()
+        .stdout("3\n1\n");
+    Ok(())
+}
+
+// --------------------------------------------------
+#[test]
+fn null_after_filename() -> TestResult {
+    Command::cargo_bin(PRG)?
+        .args(["--null", "-c", "The", BUSTLE, FOX])
+        .assert()
+        .success()
+        .stdout("tests/inputs/bustle.txt\x003\ntests/inputs/fox.txt\x001\n");
+    Ok(())
+}
+
+// --------------------------------------------------
+#[test]
+fn files_with_matches() -> TestResult {
+    let output = Command::cargo_bin(PRG)?
+        .args(["-rl", "the", INPUTS_DIR])
+        .output()?;
+    assert!(output.status.success());
+
+    let stdout = String::from_utf8(output.stdout)?.replace('\\', "/");
+    let mut files: Vec<&str> = stdout.lines().collect();
+    files.sort();
+    assert_eq!(files, vec![BUSTLE, FOX, NOBODY]);
+    Ok(())
+}
+
+// --------------------------------------------------
+#[test]
+fn files_without_match() -> TestResult {
+    Command::cargo_bin(PRG)?
+        .args(["-L", "the", BUSTLE, EMPTY, FOX])
+        .assert()
+        .stdout("tests/inputs/empty.txt\n");
+    Ok(())
+}
+
+// --------------------------------------------------
+#[test]
+fn files_with_matches_no_context() -> TestResult {
+    Command::cargo_bin(PRG)?
+        .args(["-l", "-B1", "morning", BUSTLE, FOX])
+        .assert()
+        .success()
+        .stdout("tests/inputs/bustle.txt\n");
+    Command::cargo_bin(PRG)?
+        .args(["-L", "-B1", "morning", BUSTLE, FOX])
+        .assert()
+        .stdout("tests/inputs/fox.txt\n");
+    Ok(())
+}
+
+// --------------------------------------------------
+#[test]
+fn files_with_matches_null() -> TestResult {
+    Command::cargo_bin(PRG)?
+        .args(["-lZ", "The", BUSTLE, EMPTY, FOX])
+        .assert()
+        .success()
+        .stdout("tests/inputs/bustle.txt\0tests/inputs/fox.txt\0");
+    Ok(())
+}
+
+// --------------------------------------------------
+#[test]
+fn max_count_after_context() -> TestResult {
+    run(
+        &["-m", "1", "-A", "2", "-i", "the", BUSTLE],
+        "tests/expected/bustle.txt.the.max_count1.after2",
+    )
+}
+
+// --------------------------------------------------
+#[test]
+fn max_count_caps_count() -> TestResult {
+    Command::cargo_bin(PRG)?
+        .args(["-c", "-m", "2", "-i", "the", BUSTLE, FOX])
+        .assert()
+        .success()
+        .stdout("tests/inputs/bustle.txt:2\ntests/inputs/fox.txt:1\n");
+    Ok(())
+}
+
+// --------------------------------------------------
+#[test]
+fn dies_bad_max_count() -> TestResult {
+    Command::cargo_bin(PRG)?
+        .args(["-m", "x", "fox", FOX])
+        .assert()
+        .code(2)
+        .stderr(predicate::str::contains("Invalid max count \"x\""));
+    Ok(())
+}
+
+// --------------------------------------------------
+#[test]
+fn exit_status() -> TestResult {
+    Command::cargo_bin(PRG)?.args(["fox", FOX]).assert().code(0);
+    Command::cargo_bin(PRG)?.args(["cat", FOX]).assert().code(1);
+
+    //an error wins over the files that matched
+    let bad = gen_bad_file();
+    Command::cargo_bin(PRG)?
+        .args(["fox", FOX, &bad])
+        .assert()
+        .code(2)
+        .stdout("tests/inputs/fox.txt:The quick brown fox jumps over the lazy dog.\n");
+
+    //so does a usage error
+    Command::cargo_bin(PRG)?
+        .args(["--bogus", "fox", FOX])
+        .assert()
+        .code(2);
+    Ok(())
+}
+
+// --------------------------------------------------
+#[test]
+fn quiet() -> TestResult {
+    Command::cargo_bin(PRG)?
+        .args(["-q", "-c", "fox", FOX])
+        .assert()
+        .code(0)
+        .stdout("");
+    Command::cargo_bin(PRG)?
+        .args(["-q", "cat", FOX])
+        .assert()
+        .code(1)
+        .stdout("");
+
+    //a match is enough, even with errors along the way
+    let bad = gen_bad_file();
+    Command::cargo_bin(PRG)?
+        .args(["--quiet", "fox", &bad, FOX])
+        .assert()
+        .code(0)
+        .stdout("");
+    Ok(())
+}
+
+// --------------------------------------------------
+#[test]
+fn multiline() -> TestResult {
+    run(
+        &["-U", "-n", r"death\n.*\n.*earth", BUSTLE],
+        "tests/expected/bustle.txt.multiline",
+    )?;
+
+    //lines are searched one by one without -U
+    Command::cargo_bin(PRG)?
+        .args(["-n", r"death\n.*\n.*earth", BUSTLE])
+        .assert()
+        .code(1)
+        .stdout("");
+    Ok(())
+}
+
+// --------------------------------------------------
+#[test]
+fn multiline_dotall() -> TestResult {
+    run(
+        &[
+            "--multiline",
+            "--multiline-dotall",
+            "-n",
+            "death.*earth",
+            BUSTLE,
+        ],
+        "tests/expected/bustle.txt.multiline",
+    )
+}
+
+// --------------------------------------------------
+#[test]
+fn replace() -> TestResult {
+    Command::cargo_bin(PRG)?
+        .args(["--replace", "$2 ${first}", r"(?P<first>\w+) (fox)", FOX])
+        .assert()
+        .success()
+        .stdout("The quick fox brown jumps over the lazy dog.\n");
+
+    //the file itself is left alone
+    assert_eq!(
+        fs::read_to_string(FOX)?,
+        "The quick brown fox jumps over the lazy dog.\n"
+    );
+    Ok(())
+}
+
+// --------------------------------------------------
+#[test]
+fn search_zip() -> TestResult {
+    let files =
+        ["gz", "bz2", "xz", "zst"].map(|ext| format!("tests/compressed/bustle.txt.{}", ext));
+    let mut args = vec!["-z", "-n", "-i", "the"];
+    args.extend(files.iter().map(|file| file.as_str()));
+    run(&args, "tests/expected/bustle.txt.compressed")
+}
+
+// --------------------------------------------------
+#[test]
+fn compressed_without_search_zip() -> TestResult {
+    Command::cargo_bin(PRG)?
+        .args(["the", "tests/compressed/bustle.txt.xz"])
+        .assert()
+        .code(1);
+    Ok(())
+}
+
+// --------------------------------------------------
+#[test]
+fn word_regexp_insensitive() -> TestResult {
+    run(
+        &["-w", "-i", "-o", "the", BUSTLE, NOBODY],
+        "tests/expected/bustle.nobody.the.word.insensitive",
+    )
+}
+
+// --------------------------------------------------
+#[test]
+fn word_regexp_later_match() -> TestResult {
+    //the first "foo" is part of a word, but the second one counts
+    Command::cargo_bin(PRG)?
+        .args(["-w", "-o", "-b", "foo"])
+        .write_stdin("foobar foo\nfoo_bar\n")
+        .assert()
+        .success()
+        .stdout("7:foo\n");
+    Ok(())
+}
+
+// --------------------------------------------------
+#[test]
+fn line_regexp() -> TestResult {
+    Command::cargo_bin(PRG)?
+        .args(["-x", "-F", "-i", "-e", "A.B", "-e", "c"])
+        .write_stdin("a.b\na.b c\naxb\nC\n")
+        .assert()
+        .success()
+        .stdout("a.b\nC\n");
+    Ok(())
+}
+
+// --------------------------------------------------
+#[test]
+fn count_matches() -> TestResult {
+    //-c counts lines, --count-matches every match on them
+    Command::cargo_bin(PRG)?
+        .args(["-c", "-i", "the", BUSTLE, FOX])
+        .assert()
+        .success()
+        .stdout("tests/inputs/bustle.txt:3\ntests/inputs/fox.txt:1\n");
+    Command::cargo_bin(PRG)?
+        .args(["--count-matches", "-i", "the", BUSTLE, FOX])
+        .assert()
+        .success()
+        .stdout("tests/inputs/bustle.txt:4\ntests/inputs/fox.txt:2\n");
+    Ok(())
+}
+
+// --------------------------------------------------
+#[test]
+fn stats() -> TestResult {
+    let output = Command::cargo_bin(PRG)?
+        .args(["--stats", "-i", "the", BUSTLE, EMPTY, FOX])
+        .output()?;
+    assert!(output.status.success());
+
+    let stderr = String::from_utf8(output.stderr)?;
+    let lines: Vec<&str> = stderr.lines().collect();
+    assert_eq!(
+        lines[..6],
+        [
+            "3 files searched",
+            "2 files matched",
+            "10 lines searched",
+            "238 bytes searched",
+            "4 matched lines",
+            "6 matches",
+        ]
+    );
+    assert!(lines[6].ends_with(" seconds"));
+    Ok(())
+}
+
+// --------------------------------------------------
+#[test]
+fn utf16_bom() -> TestResult {
+    run(
+        &[
+            "-n",
+            "-i",
+            "the",
+            "tests/encoded/bustle.utf16le.txt",
+            "tests/encoded/bustle.utf16be.txt",
+        ],
+        "tests/expected/bustle.txt.utf16",
+    )
+}
+
+// --------------------------------------------------
+#[test]
+fn encoding() -> TestResult {
+    let file = "tests/encoded/cafe.latin1.txt";
+    Command::cargo_bin(PRG)?
+        .args(["-E", "latin1", "-b", "crème", file])
+        .assert()
+        .success()
+        .stdout("0:café crème\n");
+
+    //without it, the file is searched as the bytes it holds
+    Command::cargo_bin(PRG)?
+        .args(["crème", file])
+        .assert()
+        .code(1);
+    Ok(())
+}
+
+// --------------------------------------------------
+#[test]
+fn dies_bad_encoding() -> TestResult {
+    Command::cargo_bin(PRG)?
+        .args(["--encoding", "bogus", "fox", FOX])
+        .assert()
+        .code(2)
+        .stderr(predicate::str::contains("Invalid encoding \"bogus\""));
+    Ok(())
+}